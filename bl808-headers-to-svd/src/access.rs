@@ -0,0 +1,121 @@
+use anyhow::{anyhow, Result};
+use svd_rs::{Access, ModifiedWriteValues, ReadAction};
+
+/// SVD semantics of a BL808 field access code
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccessMode {
+    pub access: Option<Access>,
+    pub modified_write_values: Option<ModifiedWriteValues>,
+    pub read_action: Option<ReadAction>,
+}
+
+impl AccessMode {
+    const fn new(access: Access) -> AccessMode {
+        AccessMode {
+            access: Some(access),
+            modified_write_values: None,
+            read_action: None,
+        }
+    }
+
+    const fn write(mut self, modified_write_values: ModifiedWriteValues) -> AccessMode {
+        self.modified_write_values = Some(modified_write_values);
+        self
+    }
+
+    const fn read(mut self, read_action: ReadAction) -> AccessMode {
+        self.read_action = Some(read_action);
+        self
+    }
+}
+
+/// Maps an access code from the reference manual or the SDK headers, e.g. `r/w`, `w1c`
+/// or `RWAC`. Codes are case insensitive.
+pub fn parse_access_mode(code: &str) -> Result<AccessMode> {
+    use Access::*;
+
+    Ok(match code.to_ascii_lowercase().as_str() {
+        "" | "rsvd" | "none" => AccessMode::default(),
+        "r" | "ro" => AccessMode::new(ReadOnly),
+        // Set by hardware during reset or from eFuse, software can only read it
+        "hwinit" => AccessMode::new(ReadOnly),
+        "w" | "wo" => AccessMode::new(WriteOnly),
+        "r/w" | "rw" => AccessMode::new(ReadWrite),
+        // Status bit, reads clear it
+        "roc" => AccessMode::new(ReadOnly).read(ReadAction::Clear),
+        "roc/rw" => AccessMode::new(ReadWrite).read(ReadAction::Clear),
+        // Writing 1 clears the bit, writing 0 has no effect
+        "rw1c" => AccessMode::new(ReadWrite).write(ModifiedWriteValues::OneToClear),
+        "w1c" => AccessMode::new(WriteOnly).write(ModifiedWriteValues::OneToClear),
+        // Self clearing pulse, writing 1 triggers it and writing 0 has no effect
+        "w1p" => AccessMode::new(WriteOnly).write(ModifiedWriteValues::OneToSet),
+        // Writing 1 starts an action, hardware clears the bit once it's done
+        "rwac" => AccessMode::new(ReadWrite).write(ModifiedWriteValues::OneToSet),
+        _ => return Err(anyhow!("Unknown access mode: {}", code)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(code: &str) -> Option<Access> {
+        parse_access_mode(code).unwrap().access
+    }
+
+    #[test]
+    fn plain_access() {
+        assert_eq!(access("r"), Some(Access::ReadOnly));
+        assert_eq!(access("RO"), Some(Access::ReadOnly));
+        assert_eq!(access("hwinit"), Some(Access::ReadOnly));
+        assert_eq!(access("w"), Some(Access::WriteOnly));
+        assert_eq!(access("r/w"), Some(Access::ReadWrite));
+        assert_eq!(access("RW"), Some(Access::ReadWrite));
+        assert_eq!(
+            parse_access_mode("r/w").unwrap().modified_write_values,
+            None
+        );
+        assert_eq!(parse_access_mode("r/w").unwrap().read_action, None);
+    }
+
+    #[test]
+    fn reserved_fields_have_no_access() {
+        for code in ["", "rsvd", "RSVD", "none"] {
+            assert_eq!(parse_access_mode(code).unwrap(), AccessMode::default());
+        }
+    }
+
+    #[test]
+    fn side_effects() {
+        let roc = parse_access_mode("roc").unwrap();
+        assert_eq!(roc.access, Some(Access::ReadOnly));
+        assert_eq!(roc.read_action, Some(ReadAction::Clear));
+        assert_eq!(
+            parse_access_mode("ROC/RW").unwrap().access,
+            Some(Access::ReadWrite)
+        );
+
+        let w1c = parse_access_mode("w1c").unwrap();
+        assert_eq!(w1c.access, Some(Access::WriteOnly));
+        assert_eq!(
+            w1c.modified_write_values,
+            Some(ModifiedWriteValues::OneToClear)
+        );
+        assert_eq!(access("rw1c"), Some(Access::ReadWrite));
+
+        for code in ["w1p", "RWAC"] {
+            assert_eq!(
+                parse_access_mode(code).unwrap().modified_write_values,
+                Some(ModifiedWriteValues::OneToSet)
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_codes() {
+        for code in ["rw1s", "r/o", "read"] {
+            let error = parse_access_mode(code).unwrap_err();
+            assert_eq!(error.to_string(), format!("Unknown access mode: {}", code));
+        }
+    }
+}
@@ -0,0 +1,1082 @@
+//! Struct and union declarations of a C header.
+//!
+//! Only what describes memory layout is parsed: struct and union definitions, typedefs
+//! and their members, with the comments around each member. Everything else (functions,
+//! enums, macros, initializers) is skipped.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use anyhow::{anyhow, Result};
+use pest::Parser;
+
+use crate::diagnostic::Diagnostic;
+use crate::literal::parse_literal;
+
+#[derive(Parser)]
+#[grammar = "c_struct.pest"] // relative to src
+struct CTokenParser;
+
+/// Qualifiers and CMSIS style access macros that don't change the layout
+const QUALIFIERS: &[&str] = &[
+    "volatile", "const", "static", "extern", "register", "__IO", "__I", "__O", "__IM", "__OM",
+    "__IOM",
+];
+
+/// Words of builtin types, they're never the name of a declarator
+const TYPE_KEYWORDS: &[&str] = &[
+    "unsigned", "signed", "char", "short", "int", "long", "float", "double", "_Bool",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TokenKind {
+    Comment,
+    Preprocessor,
+    String,
+    Identifier,
+    Number,
+    Punctuation,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    /// Byte offset of the token in the header
+    pub start: usize,
+    /// Line the token starts on, counted from 1
+    pub line: usize,
+}
+
+impl<'a> Token<'a> {
+    /// Bytes of the token in the text it was read from
+    pub fn span(&self) -> Range<usize> {
+        self.start..self.start + self.text.len()
+    }
+
+    /// Text of a comment without its delimiters
+    pub fn comment_text(&self) -> &'a str {
+        let text = self.text;
+        let text = match text.strip_prefix("/*") {
+            Some(block) => block.strip_suffix("*/").unwrap_or(block),
+            None => text.strip_prefix("//").unwrap_or(text),
+        };
+        text.trim()
+    }
+
+    /// Whether this is the code token `text`
+    pub fn is(&self, text: &str) -> bool {
+        self.kind != TokenKind::Comment && self.text == text
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum AggregateKind {
+    Struct,
+    Union,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Aggregate<'a> {
+    pub kind: AggregateKind,
+    pub tag: Option<&'a str>,
+    pub members: Vec<Member<'a>>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum CType<'a> {
+    /// A scalar type by its words, `uint32_t` or `unsigned int`
+    Scalar(String),
+    Aggregate(Aggregate<'a>),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Member<'a> {
+    pub ty: CType<'a>,
+    /// `None` for anonymous members and unnamed bitfields
+    pub name: Option<&'a str>,
+    /// Array dimensions, outermost first
+    pub dimensions: Vec<u64>,
+    pub bit_width: Option<u32>,
+    /// Comments between the previous member and this one
+    pub leading_comments: Vec<Token<'a>>,
+    /// Comment on the same line, after the `;`
+    pub trailing_comment: Option<Token<'a>>,
+    /// Line the declaration starts on
+    pub line: usize,
+    /// Bytes of the member's name, or of the declaration's first token if it has none
+    pub span: Range<usize>,
+}
+
+impl Member<'_> {
+    /// Number of elements, 1 for members that aren't arrays
+    pub fn element_count(&self) -> u64 {
+        self.dimensions.iter().product()
+    }
+}
+
+/// Byte layout of an aggregate
+#[derive(Debug, Clone)]
+pub(crate) struct Layout {
+    /// Offset of each member, bitfields get the offset of their storage unit
+    pub offsets: Vec<u64>,
+    pub size: u64,
+    pub alignment: u64,
+}
+
+impl CType<'_> {
+    /// Size and alignment in bytes
+    pub fn size(&self) -> Result<(u64, u64)> {
+        match self {
+            CType::Scalar(name) => scalar_size(name)
+                .map(|size| (size, size))
+                .ok_or_else(|| anyhow!("Unknown size of type '{}'", name)),
+            CType::Aggregate(aggregate) => {
+                let layout = aggregate.layout()?;
+                Ok((layout.size, layout.alignment))
+            }
+        }
+    }
+}
+
+impl Aggregate<'_> {
+    /// Lays the members out the way GCC does for RISC-V: members are aligned to their
+    /// size, bitfields are packed into storage units of their type.
+    pub fn layout(&self) -> Result<Layout> {
+        let mut offsets = Vec::new();
+        let mut bit_offset: u64 = 0;
+        let mut size: u64 = 0;
+        let mut alignment: u64 = 1;
+
+        for member in &self.members {
+            let (member_size, member_alignment) = member.ty.size()?;
+            alignment = alignment.max(member_alignment);
+            let unit_bits = member_size * 8;
+
+            let offset = match (self.kind, member.bit_width) {
+                (AggregateKind::Union, _) => {
+                    size = size.max(member_size * member.element_count());
+                    0
+                }
+                (AggregateKind::Struct, Some(width)) => {
+                    // A bitfield that would straddle a storage unit starts the next one
+                    if width == 0 || bit_offset % unit_bits + width as u64 > unit_bits {
+                        bit_offset = bit_offset.next_multiple_of(unit_bits);
+                    }
+                    let offset = bit_offset / unit_bits * member_size;
+                    bit_offset += width as u64;
+                    offset
+                }
+                (AggregateKind::Struct, None) => {
+                    let offset = bit_offset.div_ceil(8).next_multiple_of(member_alignment);
+                    bit_offset = (offset + member_size * member.element_count()) * 8;
+                    offset
+                }
+            };
+            offsets.push(offset);
+        }
+
+        if self.kind == AggregateKind::Struct {
+            size = bit_offset.div_ceil(8);
+        }
+
+        Ok(Layout {
+            offsets,
+            size: size.next_multiple_of(alignment),
+            alignment,
+        })
+    }
+
+    /// Whether any member is a bitfield
+    pub fn has_bitfields(&self) -> bool {
+        self.members.iter().any(|m| m.bit_width.is_some())
+    }
+}
+
+/// Size in bytes of a scalar type on the BL808 cores
+pub(crate) fn scalar_size(name: &str) -> Option<u64> {
+    Some(match unsigned_name(name) {
+        "uint8_t" | "int8_t" | "char" | "bool" | "_Bool" => 1,
+        "uint16_t" | "int16_t" | "short" | "short int" => 2,
+        "uint32_t" | "int32_t" | "" | "int" | "float" => 4,
+        "uint64_t" | "int64_t" | "long long" | "long long int" | "double" => 8,
+        _ => return None,
+    })
+}
+
+/// `name` without `signed` or `unsigned`
+fn unsigned_name(name: &str) -> &str {
+    name.strip_prefix("unsigned")
+        .or_else(|| name.strip_prefix("signed"))
+        .unwrap_or(name)
+        .trim()
+}
+
+/// Whether `ty` is a `long`, 4 bytes on the E907 and E902 but 8 on the C906, which reads
+/// the same headers
+fn is_long(ty: &CType) -> bool {
+    matches!(ty, CType::Scalar(name) if matches!(unsigned_name(name), "long" | "long int"))
+}
+
+/// File scope declarations of a header
+#[derive(Debug)]
+pub(crate) struct CHeader<'a> {
+    /// Struct and union definitions at file scope, in the order they're defined
+    pub aggregates: Vec<Aggregate<'a>>,
+}
+
+impl<'a> CHeader<'a> {
+    pub fn parse(text: &'a str) -> Result<CHeader<'a>> {
+        let tokens = tokenize(text)?;
+        let mut parser = DeclarationParser {
+            text,
+            tokens: &tokens,
+            pos: 0,
+            typedefs: HashMap::new(),
+            tags: HashMap::new(),
+        };
+
+        let mut aggregates = Vec::new();
+        while let Some(token) = parser.next_code() {
+            match token.text {
+                "typedef" => {
+                    if let Some(aggregate) = parser.typedef()? {
+                        aggregates.push(aggregate);
+                    }
+                }
+                "struct" | "union" => {
+                    if let Some(aggregate) = parser.aggregate_definition(token)? {
+                        aggregates.push(aggregate);
+                    }
+                }
+                // The declarations of `extern "C" { ... }` are at file scope, its closing
+                // brace is skipped like any other stray token
+                "extern"
+                    if parser
+                        .peek_code()
+                        .is_some_and(|t| t.kind == TokenKind::String) =>
+                {
+                    parser.pos += 1;
+                    parser.accept("{");
+                }
+                // Function bodies, enumerator lists and initializers
+                "{" => parser.skip_block()?,
+                _ => {}
+            }
+        }
+
+        Ok(CHeader { aggregates })
+    }
+}
+
+pub(crate) fn tokenize(text: &str) -> Result<Vec<Token<'_>>> {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+
+    let tokens = CTokenParser::parse(Rule::tokens, text)
+        .map_err(|e| Diagnostic::from_pest(text, e))?
+        .next()
+        .unwrap();
+    Ok(tokens
+        .into_inner()
+        .filter_map(|pair| {
+            let kind = match pair.as_rule() {
+                Rule::comment => TokenKind::Comment,
+                Rule::preprocessor => TokenKind::Preprocessor,
+                Rule::string => TokenKind::String,
+                Rule::identifier => TokenKind::Identifier,
+                Rule::number => TokenKind::Number,
+                Rule::punctuation => TokenKind::Punctuation,
+                _ => return None,
+            };
+            let start = pair.as_span().start();
+            Some(Token {
+                kind,
+                text: pair.as_str(),
+                start,
+                line: line_starts.partition_point(|&line_start| line_start <= start),
+            })
+        })
+        .collect())
+}
+
+/// Drops the tokens in conditional blocks that don't apply when exactly the macros of
+/// `defined` out of `known` are defined, e.g. the `#if defined(CPU_D0)` blocks when
+/// building for M0. Conditions that don't involve `known` macros keep all their branches.
+pub(crate) fn select_tokens<'a>(
+    tokens: &[Token<'a>],
+    known: &[&str],
+    defined: &[&str],
+) -> Vec<Token<'a>> {
+    // Per open conditional: whether its condition is known, whether a branch was taken
+    // and whether the current branch applies
+    let mut stack: Vec<(bool, bool, bool)> = Vec::new();
+    let mut selected = Vec::new();
+
+    for token in tokens {
+        if token.kind == TokenKind::Preprocessor {
+            let directive = token.text[1..].trim_start();
+            let keyword_end = directive
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(directive.len());
+            let (keyword, condition) = directive.split_at(keyword_end);
+            let condition = match keyword {
+                "ifdef" => Some(format!("defined({})", condition.trim())),
+                "ifndef" => Some(format!("!defined({})", condition.trim())),
+                "if" | "elif" => Some(condition.to_string()),
+                _ => None,
+            };
+            let value = condition.and_then(|c| evaluate_condition(&c, known, defined));
+
+            match keyword {
+                "if" | "ifdef" | "ifndef" => {
+                    stack.push((value.is_some(), value == Some(true), value != Some(false)))
+                }
+                "elif" => {
+                    if let Some((known, taken, active)) = stack.last_mut() {
+                        *active = !*known || (!*taken && value != Some(false));
+                        *taken |= *active;
+                    }
+                }
+                "else" => {
+                    if let Some((known, taken, active)) = stack.last_mut() {
+                        *active = !*known || !*taken;
+                    }
+                }
+                "endif" => {
+                    stack.pop();
+                }
+                _ => {}
+            }
+            if matches!(
+                keyword,
+                "if" | "ifdef" | "ifndef" | "elif" | "else" | "endif"
+            ) {
+                continue;
+            }
+        }
+
+        if stack.iter().all(|(_, _, active)| *active) {
+            selected.push(*token);
+        }
+    }
+
+    selected
+}
+
+/// Evaluates a preprocessor condition over `known` macros, `None` if it depends on others
+fn evaluate_condition(condition: &str, known: &[&str], defined: &[&str]) -> Option<bool> {
+    // Replace defined(X) and defined X by 1 or 0, then evaluate ! && || and parentheses
+    let mut expression = String::new();
+    let mut rest = condition.split("//").next().unwrap_or_default().trim();
+    while let Some(start) = rest.find("defined") {
+        expression.push_str(&rest[..start]);
+        let after = rest[start + "defined".len()..].trim_start();
+        let (name, remainder) = match after.strip_prefix('(') {
+            Some(inner) => inner.split_once(')')?,
+            None => after.split_at(
+                after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len()),
+            ),
+        };
+        let name = name.trim();
+        if !known.contains(&name) {
+            return None;
+        }
+        expression.push_str(if defined.contains(&name) {
+            " 1 "
+        } else {
+            " 0 "
+        });
+        rest = remainder;
+    }
+    expression.push_str(rest);
+
+    let expression = expression
+        .replace("||", " | ")
+        .replace("&&", " & ")
+        .replace('!', " ! ")
+        .replace('(', " ( ")
+        .replace(')', " ) ");
+    let tokens: Vec<&str> = expression.split_whitespace().collect();
+    let mut pos = 0;
+    let value = condition_or(&tokens, &mut pos)?;
+    (pos == tokens.len()).then_some(value)
+}
+
+fn condition_or(tokens: &[&str], pos: &mut usize) -> Option<bool> {
+    let mut value = condition_and(tokens, pos)?;
+    while tokens.get(*pos) == Some(&"|") {
+        *pos += 1;
+        value |= condition_and(tokens, pos)?;
+    }
+    Some(value)
+}
+
+fn condition_and(tokens: &[&str], pos: &mut usize) -> Option<bool> {
+    let mut value = condition_not(tokens, pos)?;
+    while tokens.get(*pos) == Some(&"&") {
+        *pos += 1;
+        value &= condition_not(tokens, pos)?;
+    }
+    Some(value)
+}
+
+fn condition_not(tokens: &[&str], pos: &mut usize) -> Option<bool> {
+    let token = *tokens.get(*pos)?;
+    *pos += 1;
+    match token {
+        "!" => condition_not(tokens, pos).map(|value| !value),
+        "(" => {
+            let value = condition_or(tokens, pos)?;
+            (tokens.get(*pos) == Some(&")")).then(|| *pos += 1)?;
+            Some(value)
+        }
+        "1" => Some(true),
+        "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// A declarator of a declaration, `*name[4] : 3`
+struct Declarator<'a> {
+    name: Option<Token<'a>>,
+    pointer: bool,
+    dimensions: Vec<u64>,
+    bit_width: Option<u32>,
+}
+
+struct DeclarationParser<'t, 'a> {
+    text: &'a str,
+    tokens: &'t [Token<'a>],
+    pos: usize,
+    typedefs: HashMap<&'a str, CType<'a>>,
+    tags: HashMap<(AggregateKind, &'a str), Aggregate<'a>>,
+}
+
+impl<'a> DeclarationParser<'_, 'a> {
+    fn error_at(&self, token: Token<'a>, message: String) -> anyhow::Error {
+        Diagnostic::new(self.text, token.span(), message).into()
+    }
+
+    fn error_at_end(&self, message: String) -> anyhow::Error {
+        Diagnostic::new(self.text, self.text.len()..self.text.len(), message).into()
+    }
+
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn skip_trivia(&mut self) {
+        while matches!(
+            self.peek().map(|t| t.kind),
+            Some(TokenKind::Comment | TokenKind::Preprocessor)
+        ) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek_code(&mut self) -> Option<Token<'a>> {
+        self.skip_trivia();
+        self.peek()
+    }
+
+    fn next_code(&mut self) -> Option<Token<'a>> {
+        let token = self.peek_code()?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    /// Consumes the next token if it is `text`
+    fn accept(&mut self, text: &str) -> bool {
+        if self.peek_code().is_some_and(|t| t.is(text)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token<'a>> {
+        match self.next_code() {
+            Some(token) if token.is(text) => Ok(token),
+            Some(token) => Err(self.error_at(
+                token,
+                format!("expected '{}' but found '{}'", text, token.text),
+            )),
+            None => Err(self.error_at_end(format!("expected '{}' but the file ended", text))),
+        }
+    }
+
+    /// Skips a `{ ... }` block whose opening brace was already consumed
+    fn skip_block(&mut self) -> Result<()> {
+        let mut depth = 1;
+        while depth > 0 {
+            let token = self
+                .next_code()
+                .ok_or_else(|| self.error_at_end("unterminated block".to_string()))?;
+            if token.is("{") {
+                depth += 1;
+            } else if token.is("}") {
+                depth -= 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Skips qualifiers and `__attribute__((...))`
+    fn skip_qualifiers(&mut self) -> Result<()> {
+        while let Some(token) = self.peek_code() {
+            if token.kind == TokenKind::Identifier && QUALIFIERS.contains(&token.text) {
+                self.pos += 1;
+            } else if token.is("__attribute__") {
+                self.pos += 1;
+                self.skip_parentheses()?;
+            } else {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn skip_parentheses(&mut self) -> Result<()> {
+        self.expect("(")?;
+        let mut depth = 1;
+        while depth > 0 {
+            let token = self
+                .next_code()
+                .ok_or_else(|| self.error_at_end("unterminated parentheses".to_string()))?;
+            if token.is("(") {
+                depth += 1;
+            } else if token.is(")") {
+                depth -= 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// `struct` or `union` at file scope, the keyword was already consumed. Returns the
+    /// aggregate if this defines one.
+    fn aggregate_definition(&mut self, keyword: Token<'a>) -> Result<Option<Aggregate<'a>>> {
+        let (ty, defined) = self.aggregate_specifier(keyword)?;
+        match ty {
+            CType::Aggregate(aggregate) if defined => Ok(Some(aggregate)),
+            _ => Ok(None),
+        }
+    }
+
+    /// `typedef`, the keyword was already consumed. Returns the aggregate if this defines
+    /// one, anonymous ones are named after the typedef.
+    fn typedef(&mut self) -> Result<Option<Aggregate<'a>>> {
+        let (mut ty, defined) = self.type_specifier()?;
+        let declarators = self.declarators()?;
+        self.expect(";")?;
+
+        if let CType::Aggregate(aggregate) = &mut ty {
+            if aggregate.tag.is_none() {
+                aggregate.tag = declarators
+                    .first()
+                    .and_then(|d| d.name)
+                    .map(|name| name.text);
+            }
+        }
+        for declarator in declarators {
+            if let (Some(name), false) = (declarator.name, declarator.pointer) {
+                self.typedefs.insert(name.text, ty.clone());
+            }
+        }
+
+        match ty {
+            CType::Aggregate(aggregate) if defined => Ok(Some(aggregate)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Parses a type. Also returns whether it defined a struct or union.
+    fn type_specifier(&mut self) -> Result<(CType<'a>, bool)> {
+        self.skip_qualifiers()?;
+        let first = self
+            .peek_code()
+            .ok_or_else(|| self.error_at_end("expected a type but the file ended".to_string()))?;
+        if first.is("struct") || first.is("union") {
+            self.pos += 1;
+            return self.aggregate_specifier(first);
+        }
+
+        // Every identifier but the last names the type, the last one is the declarator
+        // unless it's part of a builtin type, like in `unsigned int : 4`
+        let mut words = Vec::new();
+        let mut word_positions = Vec::new();
+        while let Some(token) = self.peek_code() {
+            if token.kind != TokenKind::Identifier {
+                break;
+            }
+            if QUALIFIERS.contains(&token.text) {
+                self.pos += 1;
+                continue;
+            }
+            word_positions.push(self.pos);
+            words.push(token.text);
+            self.pos += 1;
+        }
+        if words.is_empty() {
+            return Err(self.error_at(first, format!("expected a type but found '{}'", first.text)));
+        }
+        if words.len() > 1 && !words.last().is_some_and(|w| TYPE_KEYWORDS.contains(w)) {
+            words.pop();
+            self.pos = word_positions.pop().unwrap();
+        }
+
+        let name = words.join(" ");
+        let ty = match self.typedefs.get(name.as_str()) {
+            Some(ty) => ty.clone(),
+            None => CType::Scalar(name),
+        };
+        Ok((ty, false))
+    }
+
+    /// The rest of a struct or union specifier after its keyword
+    fn aggregate_specifier(&mut self, keyword: Token<'a>) -> Result<(CType<'a>, bool)> {
+        let kind = if keyword.is("union") {
+            AggregateKind::Union
+        } else {
+            AggregateKind::Struct
+        };
+        self.skip_qualifiers()?;
+        let tag = match self.peek_code() {
+            Some(token) if token.kind == TokenKind::Identifier => {
+                self.pos += 1;
+                Some(token.text)
+            }
+            _ => None,
+        };
+
+        if !self.accept("{") {
+            let tag = tag.ok_or_else(|| {
+                self.error_at(
+                    keyword,
+                    format!("expected a name or '{{' after '{}'", keyword.text),
+                )
+            })?;
+            // A reference to a struct defined earlier, or an incomplete type
+            let aggregate = self.tags.get(&(kind, tag)).cloned().unwrap_or(Aggregate {
+                kind,
+                tag: Some(tag),
+                members: Vec::new(),
+            });
+            return Ok((CType::Aggregate(aggregate), false));
+        }
+
+        let members = self.members()?;
+        self.expect("}")?;
+        self.skip_qualifiers()?;
+        let aggregate = Aggregate { kind, tag, members };
+        if let Some(tag) = tag {
+            self.tags.insert((kind, tag), aggregate.clone());
+        }
+        Ok((CType::Aggregate(aggregate), true))
+    }
+
+    /// Members of a struct or union up to its closing brace
+    fn members(&mut self) -> Result<Vec<Member<'a>>> {
+        let mut members = Vec::new();
+
+        loop {
+            let mut leading_comments = Vec::new();
+            while let Some(token) = self.peek() {
+                match token.kind {
+                    TokenKind::Comment => leading_comments.push(token),
+                    TokenKind::Preprocessor => {}
+                    _ => break,
+                }
+                self.pos += 1;
+            }
+
+            let first = self
+                .peek()
+                .ok_or_else(|| self.error_at_end("unterminated struct or union".to_string()))?;
+            if first.is("}") {
+                return Ok(members);
+            }
+            if self.accept(";") {
+                continue;
+            }
+
+            let (ty, _) = self.type_specifier()?;
+            if is_long(&ty) {
+                return Err(self.error_at(
+                    first,
+                    "'long' has no single size on the BL808, 4 bytes on M0 and LP but 8 on D0, use a fixed width type".to_string(),
+                ));
+            }
+            let declarators = self.declarators()?;
+            let semicolon = self.expect(";")?;
+            let trailing_comment = match self.peek() {
+                Some(token) if token.kind == TokenKind::Comment && token.line == semicolon.line => {
+                    self.pos += 1;
+                    Some(token)
+                }
+                _ => None,
+            };
+
+            let last = declarators.len() - 1;
+            for (i, declarator) in declarators.into_iter().enumerate() {
+                if declarator.pointer {
+                    return Err(
+                        self.error_at(first, "pointer members aren't supported".to_string())
+                    );
+                }
+                members.push(Member {
+                    ty: ty.clone(),
+                    name: declarator.name.map(|name| name.text),
+                    span: declarator.name.unwrap_or(first).span(),
+                    dimensions: declarator.dimensions,
+                    bit_width: declarator.bit_width,
+                    leading_comments: leading_comments.clone(),
+                    // `a : 1, b : 1; /* ... */` documents `b`
+                    trailing_comment: trailing_comment.filter(|_| i == last),
+                    line: first.line,
+                });
+            }
+        }
+    }
+
+    /// Comma separated declarators, up to the `;`. Declarations without any, like
+    /// anonymous unions, get a single unnamed declarator.
+    fn declarators(&mut self) -> Result<Vec<Declarator<'a>>> {
+        let mut declarators = Vec::new();
+
+        loop {
+            let mut declarator = Declarator {
+                name: None,
+                pointer: false,
+                dimensions: Vec::new(),
+                bit_width: None,
+            };
+
+            loop {
+                self.skip_qualifiers()?;
+                if !self.accept("*") {
+                    break;
+                }
+                declarator.pointer = true;
+            }
+            if let Some(token) = self.peek_code() {
+                if token.kind == TokenKind::Identifier {
+                    self.pos += 1;
+                    declarator.name = Some(token);
+                }
+            }
+            while self.accept("[") {
+                declarator.dimensions.push(self.number()?);
+                self.expect("]")?;
+            }
+            if self.accept(":") {
+                declarator.bit_width = Some(self.number()? as u32);
+            }
+            self.skip_qualifiers()?;
+
+            declarators.push(declarator);
+            if !self.accept(",") {
+                return Ok(declarators);
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<u64> {
+        let token = self
+            .next_code()
+            .ok_or_else(|| self.error_at_end("expected a number but the file ended".to_string()))?;
+        if token.kind != TokenKind::Number {
+            return Err(self.error_at(
+                token,
+                format!("expected a number but found '{}'", token.text),
+            ));
+        }
+        let literal = parse_literal(token.text, 10).map_err(|e| {
+            let e = e.offset(token.start);
+            anyhow::Error::from(Diagnostic::new(self.text, e.span(), e.to_string()))
+        })?;
+        Ok(literal.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Offsets and size of the first aggregate of `text`
+    fn layout(text: &str) -> (Vec<u64>, u64) {
+        let header = CHeader::parse(text).unwrap();
+        let layout = header.aggregates[0].layout().unwrap();
+        (layout.offsets, layout.size)
+    }
+
+    #[test]
+    fn tokens() {
+        let text = "#define A \\\n  1\n/* offset */ uint32_t a; // x\n'c' \"s\" 0x1fU";
+        let tokens = tokenize(text).unwrap();
+        let kinds: Vec<_> = tokens.iter().map(|t| (t.kind, t.text, t.line)).collect();
+        assert_eq!(
+            kinds,
+            [
+                (TokenKind::Preprocessor, "#define A \\\n  1", 1),
+                (TokenKind::Comment, "/* offset */", 3),
+                (TokenKind::Identifier, "uint32_t", 3),
+                (TokenKind::Identifier, "a", 3),
+                (TokenKind::Punctuation, ";", 3),
+                (TokenKind::Comment, "// x", 3),
+                (TokenKind::String, "'c'", 4),
+                (TokenKind::String, "\"s\"", 4),
+                (TokenKind::Number, "0x1fU", 4),
+            ]
+        );
+        assert_eq!(tokens[1].comment_text(), "offset");
+        assert_eq!(tokens[1].span(), 16..28);
+    }
+
+    #[test]
+    fn unterminated_struct() {
+        let error = CHeader::parse("struct s {\n    uint32_t a;\n").unwrap_err();
+        let diagnostic = error.downcast_ref::<Diagnostic>().unwrap();
+        assert_eq!(diagnostic.message, "unterminated struct or union");
+        assert_eq!((diagnostic.line, diagnostic.column), (3, 1));
+    }
+
+    #[test]
+    fn members_and_comments() {
+        let text = "struct glb_reg {
+    /* 0x0 : soc_info0 */
+    union {
+        struct {
+            uint32_t reserved_0_26 : 27; /* [26: 0],       rsvd,        0x0 */
+            uint32_t chip_rdy      :  1; /* [   27],          r,        0x0 */
+            uint32_t id, rev;
+        } BF;
+        uint32_t WORD;
+    } soc_info0;
+    uint32_t regs[2][3];
+};";
+        let header = CHeader::parse(text).unwrap();
+        let glb = &header.aggregates[0];
+        assert_eq!(glb.tag, Some("glb_reg"));
+        assert_eq!(glb.kind, AggregateKind::Struct);
+
+        let soc_info0 = &glb.members[0];
+        assert_eq!(soc_info0.name, Some("soc_info0"));
+        assert_eq!(
+            soc_info0.leading_comments[0].comment_text(),
+            "0x0 : soc_info0"
+        );
+        let CType::Aggregate(union) = &soc_info0.ty else {
+            panic!("soc_info0 isn't a union");
+        };
+        let CType::Aggregate(bitfields) = &union.members[0].ty else {
+            panic!("BF isn't a struct");
+        };
+        let chip_rdy = &bitfields.members[1];
+        assert_eq!(chip_rdy.name, Some("chip_rdy"));
+        assert_eq!(chip_rdy.bit_width, Some(1));
+        assert_eq!(
+            chip_rdy.trailing_comment.unwrap().comment_text(),
+            "[   27],          r,        0x0"
+        );
+        // Only the last declarator gets the comment after the `;`
+        assert_eq!(bitfields.members[2].name, Some("id"));
+        assert_eq!(bitfields.members[3].name, Some("rev"));
+
+        assert_eq!(glb.members[1].dimensions, [2, 3]);
+        assert_eq!(glb.members[1].element_count(), 6);
+    }
+
+    #[test]
+    fn typedefs() {
+        let text = "typedef unsigned int u32;
+typedef struct { u32 a; } anon_t;
+typedef struct tagged { uint8_t b; } tagged_t, *tagged_p;
+struct outer { anon_t x; struct tagged y; tagged_t z; };";
+        let header = CHeader::parse(text).unwrap();
+        let tags: Vec<_> = header.aggregates.iter().map(|a| a.tag).collect();
+        assert_eq!(tags, [Some("anon_t"), Some("tagged"), Some("outer")]);
+        assert_eq!(layout(text).1, 4);
+        assert_eq!(header.aggregates[2].layout().unwrap().offsets, [0, 4, 5]);
+    }
+
+    #[test]
+    fn unnamed_bitfields() {
+        let text = "struct s { unsigned int a : 4; unsigned int : 4; unsigned b : 8; uint32_t : 0; unsigned int c : 1; };";
+        let header = CHeader::parse(text).unwrap();
+        let members: Vec<_> = header.aggregates[0]
+            .members
+            .iter()
+            .map(|m| (m.name, m.bit_width))
+            .collect();
+        assert_eq!(
+            members,
+            [
+                (Some("a"), Some(4)),
+                (None, Some(4)),
+                (Some("b"), Some(8)),
+                (None, Some(0)),
+                (Some("c"), Some(1)),
+            ]
+        );
+        let CType::Scalar(ty) = &header.aggregates[0].members[1].ty else {
+            panic!("not a scalar");
+        };
+        assert_eq!(ty, "unsigned int");
+        assert_eq!(layout(text), (vec![0, 0, 0, 4, 4], 8));
+    }
+
+    #[test]
+    fn extern_c_is_transparent() {
+        let text = "#ifdef __cplusplus
+extern \"C\" {
+#endif
+static inline void f(void) { int x = 1; }
+struct a { uint32_t x; };
+#ifdef __cplusplus
+}
+#endif
+struct b { uint16_t y; };";
+        let header = CHeader::parse(text).unwrap();
+        let tags: Vec<_> = header.aggregates.iter().map(|a| a.tag).collect();
+        assert_eq!(tags, [Some("a"), Some("b")]);
+    }
+
+    #[test]
+    fn skipped_declarations() {
+        let text = "enum e { A = 1, B };
+int table[2] = { 1, 2 };
+void f(struct a *p);
+struct c { uint32_t x; } __attribute__((packed));";
+        let header = CHeader::parse(text).unwrap();
+        let tags: Vec<_> = header.aggregates.iter().map(|a| a.tag).collect();
+        assert_eq!(tags, [Some("c")]);
+    }
+
+    #[test]
+    fn pointer_members_are_errors() {
+        let error = CHeader::parse("struct s {\n    uint32_t *p;\n};").unwrap_err();
+        let diagnostic = error.downcast_ref::<Diagnostic>().unwrap();
+        assert_eq!((diagnostic.line, diagnostic.column), (2, 5));
+        assert_eq!(diagnostic.message, "pointer members aren't supported");
+    }
+
+    // The expected layouts are GCC's offsetof and sizeof. The RISC-V psABI aligns each of
+    // these types to its size and packs bitfields like the SysV ABIs do.
+
+    #[test]
+    fn long_members_are_errors() {
+        for ty in ["long", "unsigned long", "signed long int"] {
+            let text = format!("struct a {{\n    uint32_t x;\n    {} y;\n}};", ty);
+            let error = CHeader::parse(&text)
+                .unwrap_err()
+                .downcast::<Diagnostic>()
+                .unwrap();
+            assert_eq!((error.line, error.column), (3, 5));
+            assert!(error.message.starts_with("'long' has no single size"));
+        }
+
+        // long long is 8 bytes on every core
+        let header = CHeader::parse("struct a { unsigned long long x; };").unwrap();
+        assert_eq!(header.aggregates[0].layout().unwrap().size, 8);
+    }
+
+    #[test]
+    fn padding() {
+        assert_eq!(
+            layout("struct s { uint8_t a; uint32_t b; };"),
+            (vec![0, 4], 8)
+        );
+        assert_eq!(
+            layout("struct s { uint32_t a; uint8_t b; };"),
+            (vec![0, 4], 8)
+        );
+        assert_eq!(
+            layout("struct s { uint16_t a; uint32_t b[2]; uint8_t c; };"),
+            (vec![0, 4, 12], 16)
+        );
+        assert_eq!(
+            layout("struct s { uint8_t a; uint64_t b; };"),
+            (vec![0, 8], 16)
+        );
+    }
+
+    #[test]
+    fn bitfields_share_a_storage_unit() {
+        assert_eq!(
+            layout("struct s { uint32_t a : 3; uint32_t b : 29; };"),
+            (vec![0, 0], 4)
+        );
+        assert_eq!(
+            layout("struct s { uint8_t a : 4; uint32_t b : 8; };"),
+            (vec![0, 0], 4)
+        );
+        assert_eq!(
+            layout("struct s { uint8_t a; uint32_t b : 8; };"),
+            (vec![0, 0], 4)
+        );
+    }
+
+    #[test]
+    fn straddling_bitfields_start_a_new_unit() {
+        assert_eq!(
+            layout("struct s { uint32_t a : 3; uint32_t b : 30; };"),
+            (vec![0, 4], 8)
+        );
+        assert_eq!(
+            layout("struct s { uint8_t a : 4; uint8_t b : 6; };"),
+            (vec![0, 1], 2)
+        );
+        assert_eq!(
+            layout("struct s { uint16_t a : 12; uint32_t b : 24; };"),
+            (vec![0, 4], 8)
+        );
+    }
+
+    #[test]
+    fn zero_width_bitfields() {
+        assert_eq!(
+            layout("struct s { uint32_t a : 4; uint32_t : 0; uint32_t b : 4; };"),
+            (vec![0, 4, 4], 8)
+        );
+    }
+
+    #[test]
+    fn unions() {
+        let text = "union u { struct { uint32_t a : 8; uint32_t b : 24; } BF; uint32_t WORD; };";
+        assert_eq!(layout(text), (vec![0, 0], 4));
+        assert_eq!(
+            layout("union u { uint8_t a[5]; uint16_t b; };"),
+            (vec![0, 0], 6)
+        );
+    }
+
+    #[test]
+    fn conditional_tokens() {
+        let text = "#if defined(CPU_M0) || defined(CPU_LP)
+m0_lp
+#elif defined(CPU_D0)
+d0
+#else
+none
+#endif
+#ifdef OTHER
+other
+#endif";
+        let tokens = tokenize(text).unwrap();
+        let known = ["CPU_M0", "CPU_D0", "CPU_LP"];
+        let selected = |defined: &[&str]| -> Vec<&str> {
+            select_tokens(&tokens, &known, defined)
+                .iter()
+                .map(|t| t.text)
+                .collect()
+        };
+        assert_eq!(selected(&["CPU_M0"]), ["m0_lp", "other"]);
+        assert_eq!(selected(&["CPU_D0"]), ["d0", "other"]);
+        assert_eq!(selected(&[]), ["none", "other"]);
+    }
+}
@@ -0,0 +1,81 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use svd_rs::{Cpu, Endian, ValidateLevel};
+
+/// The three RISC-V cores of the BL808
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Core {
+    /// T-Head E907, RV32IMAFCP
+    M0,
+    /// T-Head C906, RV64GCV
+    D0,
+    /// T-Head E902, RV32EMC
+    LP,
+}
+
+/// `<cpu>` properties of a core, from the `[cpu.<core>]` table of the manifest
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CpuProperties {
+    pub revision: String,
+    /// Interrupt priority bits of the core's interrupt controller
+    pub priority_bits: u32,
+    /// `little`, `big`, `selectable` or `other`
+    pub endian: String,
+    /// Single precision FPU
+    pub fpu: bool,
+    pub fpu_double: bool,
+    pub dsp: bool,
+    pub icache: bool,
+    pub dcache: bool,
+}
+
+impl Core {
+    pub const ALL: [Core; 3] = [Core::M0, Core::D0, Core::LP];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Core::M0 => "M0",
+            Core::D0 => "D0",
+            Core::LP => "LP",
+        }
+    }
+
+    /// Macro the SDKs guard code for this core with
+    pub fn define(&self) -> &'static str {
+        match self {
+            Core::M0 => "CPU_M0",
+            Core::D0 => "CPU_D0",
+            Core::LP => "CPU_LP",
+        }
+    }
+
+    /// T-Head model of the core
+    pub fn model(&self) -> &'static str {
+        match self {
+            Core::M0 => "E907",
+            Core::D0 => "C906",
+            Core::LP => "E902",
+        }
+    }
+
+    /// `<cpu>` description of the core
+    pub fn cpu(&self, properties: &CpuProperties) -> Result<Cpu> {
+        let endian = Endian::parse_str(&properties.endian)
+            .ok_or_else(|| anyhow!("Unknown endianness '{}'", properties.endian))?;
+
+        Ok(Cpu::builder()
+            .name(self.model().to_string())
+            .revision(properties.revision.clone())
+            .endian(endian)
+            .mpu_present(false)
+            .fpu_present(properties.fpu)
+            .fpu_double_precision(Some(properties.fpu_double))
+            .dsp_present(Some(properties.dsp))
+            .icache_present(Some(properties.icache))
+            .dcache_present(Some(properties.dcache))
+            .nvic_priority_bits(properties.priority_bits)
+            .has_vendor_systick(false)
+            .build(ValidateLevel::Weak)?)
+    }
+}
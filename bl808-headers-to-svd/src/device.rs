@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context, Result};
+use svd_rs::{Cpu, Device, Interrupt, MaybeArray, Peripheral, PeripheralInfo, ValidateLevel};
+
+use crate::cpu::{Core, CpuProperties};
+use crate::interrupts::IrqDefinition;
+use crate::manifest::{Manifest, PeripheralEntry, SourceKind};
+use crate::memory_map::MemoryMap;
+use crate::peripheral_from_c_defines::CDefinesSource;
+use crate::peripheral_from_c_header::CHeaderSource;
+use crate::peripheral_from_doc_rst::DocRstSource;
+use crate::register_source::{RegisterBlock, RegisterSource, Translation};
+use crate::rst_markup::DescriptionFormat;
+use crate::source_roots::SourceRoots;
+
+/// Device level properties of the generated SVD
+#[derive(Debug, Clone)]
+pub struct DeviceMetadata {
+    pub name: String,
+    pub version: String,
+    pub vendor: Option<String>,
+    pub description: String,
+    pub cpu: Option<Cpu>,
+}
+
+impl Default for DeviceMetadata {
+    fn default() -> Self {
+        DeviceMetadata {
+            name: "BL808".to_string(),
+            version: "0.1".to_string(),
+            vendor: None,
+            description: "Bouffalo Labs BL808".to_string(),
+            cpu: None,
+        }
+    }
+}
+
+impl DeviceMetadata {
+    /// Metadata of the device as `core` sees it, `BL808_M0`. It has an E907 `<cpu>` if
+    /// `properties` completes it.
+    pub fn for_core(
+        &self,
+        core: Core,
+        properties: Option<&CpuProperties>,
+    ) -> Result<DeviceMetadata> {
+        Ok(DeviceMetadata {
+            name: format!("{}_{}", self.name, core.name()),
+            description: format!(
+                "{}, {} core ({})",
+                self.description,
+                core.name(),
+                core.model()
+            ),
+            cpu: properties.map(|p| core.cpu(p)).transpose()?,
+            ..self.clone()
+        })
+    }
+}
+
+/// Collects peripherals from register sources and builds the SVD device from them
+#[derive(Debug, Default, Clone)]
+pub struct DeviceAssembler {
+    peripherals: Vec<Peripheral>,
+    /// Cores that can reach a peripheral, peripherals missing here are reachable by all
+    cores: HashMap<String, Vec<Core>>,
+    /// Format of the descriptions read from the reference manual
+    description_format: DescriptionFormat,
+    /// Translated descriptions, with paths starting at the peripheral name
+    translations: Vec<Translation>,
+}
+
+impl DeviceAssembler {
+    pub fn new() -> DeviceAssembler {
+        DeviceAssembler::default()
+    }
+
+    pub fn peripherals(&self) -> &[Peripheral] {
+        &self.peripherals
+    }
+
+    /// Descriptions in a second language, `UART0.utx_config.cr_utx_en`
+    pub fn translations(&self) -> &[Translation] {
+        &self.translations
+    }
+
+    /// Format of the descriptions of peripherals added from the reference manual
+    pub fn set_description_format(&mut self, format: DescriptionFormat) {
+        self.description_format = format;
+    }
+
+    /// Restricts the peripheral `name` to `cores`
+    pub fn set_cores(&mut self, name: &str, cores: &[Core]) {
+        self.cores.insert(name.to_string(), cores.to_vec());
+    }
+
+    /// The peripherals `core` can reach
+    pub fn for_core(&self, core: Core) -> DeviceAssembler {
+        let peripherals = self
+            .peripherals
+            .iter()
+            .filter(|p| {
+                self.cores
+                    .get(&p.name)
+                    .is_none_or(|cores| cores.contains(&core))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        let translations = self
+            .translations
+            .iter()
+            .filter(|t| {
+                let peripheral = t.path.split('.').next().unwrap_or_default();
+                peripherals.iter().any(|p| p.name == peripheral)
+            })
+            .cloned()
+            .collect();
+
+        DeviceAssembler {
+            peripherals,
+            cores: self.cores.clone(),
+            description_format: self.description_format,
+            translations,
+        }
+    }
+
+    /// Adds a peripheral with the registers of `source`. `base_address` takes precedence
+    /// over the base address the source documents itself.
+    pub fn add_peripheral(
+        &mut self,
+        name: &str,
+        base_address: Option<u64>,
+        source: &dyn RegisterSource,
+    ) -> Result<()> {
+        let mut block = source.load()?;
+        self.add_translations(name, &mut block);
+        let base_address = base_address
+            .or(block.base_address)
+            .ok_or_else(|| anyhow!("No base address for peripheral {}", name))?;
+
+        let peripheral = PeripheralInfo::builder()
+            .name(name.to_string())
+            .description(block.description)
+            .registers(Some(block.registers))
+            .base_address(base_address)
+            .build(source.validate_level())?;
+        self.peripherals.push(Peripheral::Single(peripheral));
+
+        Ok(())
+    }
+
+    /// Puts the last peripheral called `name` in the group `group_name`
+    pub fn set_group_name(&mut self, name: &str, group_name: &str) -> Result<()> {
+        let peripheral = self
+            .peripherals
+            .iter_mut()
+            .rev()
+            .find(|p| p.name == name)
+            .ok_or_else(|| anyhow!("No peripheral {} to group", name))?;
+        peripheral.group_name = Some(group_name.to_string());
+
+        Ok(())
+    }
+
+    /// Merges the registers of `source` into the last peripheral called `name`
+    pub fn append_registers(&mut self, name: &str, source: &dyn RegisterSource) -> Result<()> {
+        let peripheral = self
+            .peripherals
+            .iter_mut()
+            .rev()
+            .find(|p| p.name == name)
+            .ok_or_else(|| anyhow!("No peripheral {} to append to", name))?;
+        let peripheral = match peripheral {
+            MaybeArray::Single(p) => p,
+            MaybeArray::Array(_, _) => {
+                return Err(anyhow!("Can't append to peripheral array {}", name));
+            }
+        };
+
+        let mut block = source.load()?;
+        let mut new_registers = std::mem::take(&mut block.registers);
+        match &mut peripheral.registers {
+            Some(registers) => registers.append(&mut new_registers),
+            None => peripheral.registers = Some(new_registers),
+        }
+        self.add_translations(name, &mut block);
+
+        Ok(())
+    }
+
+    fn add_translations(&mut self, name: &str, block: &mut RegisterBlock) {
+        for mut translation in block.translations.drain(..) {
+            translation.path = if translation.path.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}.{}", name, translation.path)
+            };
+            self.translations.push(translation);
+        }
+    }
+
+    /// Adds every peripheral listed in `manifest`, at the base addresses of the SDK memory
+    /// map unless the manifest gives one. Peripherals that fail don't stop the others from
+    /// being added, their errors are returned instead.
+    pub fn add_manifest(&mut self, manifest: &Manifest, roots: &SourceRoots) -> Vec<anyhow::Error> {
+        let mut errors = Vec::new();
+
+        let headers = roots.memory_map_headers();
+        if headers.is_empty() {
+            errors.push(anyhow!(
+                "No memory map header found, base addresses are not checked"
+            ));
+        }
+        let memory_map = MemoryMap::from_headers(&headers).unwrap_or_else(|e| {
+            errors.push(e);
+            MemoryMap::default()
+        });
+
+        for entry in &manifest.peripherals {
+            let added = base_address(entry, &memory_map)
+                .and_then(|base_address| {
+                    let source = entry_source(entry, &entry.file, roots, self.description_format)?;
+                    self.add_peripheral(&entry.name, base_address, source.as_ref())
+                })
+                .with_context(|| {
+                    format!(
+                        "Error processing peripheral {} from {}",
+                        entry.name, entry.file
+                    )
+                });
+            if let Err(e) = added {
+                // Nothing to append to if the peripheral itself failed
+                errors.push(e);
+                continue;
+            }
+            if !entry.cores.is_empty() {
+                self.set_cores(&entry.name, &entry.cores);
+            }
+            if let Some(group_name) = manifest.group_name(&entry.name) {
+                if let Err(e) = self.set_group_name(&entry.name, group_name) {
+                    errors.push(e);
+                }
+            }
+
+            for filename in &entry.append {
+                if let Err(e) = entry_source(entry, filename, roots, self.description_format)
+                    .and_then(|source| self.append_registers(&entry.name, source.as_ref()))
+                    .with_context(|| {
+                        format!("Error appending {} to peripheral {}", filename, entry.name)
+                    })
+                {
+                    errors.push(e);
+                }
+            }
+        }
+
+        errors
+    }
+
+    /// Attaches each interrupt to the peripheral it's named after, `UART0` or
+    /// `DMA0_INTR0` to UART0 and DMA0. Returns the interrupts no peripheral matched.
+    pub fn add_interrupts<'i>(&mut self, irqs: &'i [IrqDefinition]) -> Vec<&'i IrqDefinition> {
+        let mut unmatched = Vec::new();
+
+        for irq in irqs {
+            // The longest peripheral name that prefixes the interrupt name
+            let peripheral = self
+                .peripherals
+                .iter_mut()
+                .filter(|p| {
+                    irq.name == p.name
+                        || irq
+                            .name
+                            .strip_prefix(p.name.as_str())
+                            .is_some_and(|rest| rest.starts_with('_'))
+                })
+                .max_by_key(|p| p.name.len());
+            let Some(peripheral) = peripheral else {
+                unmatched.push(irq);
+                continue;
+            };
+
+            let interrupt = Interrupt::builder()
+                .name(irq.name.clone())
+                .description(irq.description.clone())
+                .value(irq.value)
+                .build(ValidateLevel::Weak);
+            match interrupt {
+                Ok(interrupt) => peripheral.interrupt.push(interrupt),
+                Err(_) => unmatched.push(irq),
+            }
+        }
+
+        unmatched
+    }
+
+    pub fn build(self, metadata: &DeviceMetadata, level: ValidateLevel) -> Result<Device> {
+        let mut cpu = metadata.cpu.clone();
+        if let Some(cpu) = &mut cpu {
+            cpu.device_num_interrupts = self
+                .peripherals
+                .iter()
+                .flat_map(|p| &p.interrupt)
+                .map(|interrupt| interrupt.value + 1)
+                .max();
+        }
+
+        Ok(Device::builder()
+            .name(metadata.name.clone())
+            .cpu(cpu)
+            .vendor(metadata.vendor.clone())
+            .peripherals(self.peripherals)
+            .version(metadata.version.clone())
+            .description(metadata.description.clone())
+            .address_unit_bits(8)
+            .width(32)
+            .build(level)?)
+    }
+}
+
+fn base_address(entry: &PeripheralEntry, memory_map: &MemoryMap) -> Result<Option<u64>> {
+    let define = entry.base_define();
+    let base_address = memory_map.resolve(&define, entry.base_address, entry.no_base_define)?;
+    // The reference manual documents its own addresses, headers don't
+    if base_address.is_none() && entry.source != SourceKind::Rst {
+        return Err(anyhow!(
+            "No base address, set base_address or define {} in the memory map",
+            define
+        ));
+    }
+
+    Ok(base_address)
+}
+
+fn entry_source(
+    entry: &PeripheralEntry,
+    filename: &str,
+    roots: &SourceRoots,
+    format: DescriptionFormat,
+) -> Result<Box<dyn RegisterSource>> {
+    Ok(match entry.source {
+        SourceKind::Header => {
+            let header = roots
+                .header_path(filename)
+                .ok_or_else(|| anyhow!("Header file not found: {}", filename))?;
+            Box::new(CHeaderSource::new(header))
+        }
+        SourceKind::Defines => {
+            let header = roots
+                .defines_path(filename)
+                .ok_or_else(|| anyhow!("Header file not found: {}", filename))?;
+            Box::new(CDefinesSource::new(header))
+        }
+        SourceKind::Rst => Box::new(
+            DocRstSource::new(roots.rst_path(filename, entry.language), entry.language)
+                .with_translation(roots.rst_path(filename, entry.language.other()))
+                .with_format(format),
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn every_core_has_a_cpu() {
+        let manifest =
+            Manifest::from_file(&Path::new(env!("CARGO_MANIFEST_DIR")).join("peripherals.toml"))
+                .unwrap();
+        let uart = PeripheralInfo::builder()
+            .name("UART0".to_string())
+            .base_address(0x2000_a000)
+            .build(ValidateLevel::Weak)
+            .unwrap();
+        let irqs = [IrqDefinition {
+            name: "UART0".to_string(),
+            value: 44,
+            description: None,
+        }];
+
+        for core in Core::ALL {
+            let mut assembler = DeviceAssembler::new();
+            assembler.peripherals.push(Peripheral::Single(uart.clone()));
+            assert!(assembler.add_interrupts(&irqs).is_empty());
+            let metadata = DeviceMetadata::default()
+                .for_core(core, manifest.cpu.get(core))
+                .unwrap();
+            let device = assembler.build(&metadata, ValidateLevel::Weak).unwrap();
+            let svd = svd_encoder::encode(&device).unwrap();
+
+            assert!(svd.contains("<cpu>"), "no <cpu> for {}", core.name());
+            assert!(
+                svd.contains("<nvicPrioBits>"),
+                "no <nvicPrioBits> for {}",
+                core.name()
+            );
+            assert!(svd.contains(&format!("<name>{}</name>", core.model())));
+            assert!(svd.contains("<deviceNumInterrupts>45</deviceNumInterrupts>"));
+        }
+    }
+}
@@ -0,0 +1,324 @@
+//! Errors and warnings that point into a source file, rendered like compiler errors:
+//!
+//! ```text
+//! error: Invalid bits: 31-16
+//!   --> bl_docs/BL808_RM/en/RST/uart_register.rst:21:3
+//!    |
+//! 21 | | 31-16    | cr_utx_len | r/w | 16'd0 | Length of UART TX data transfer |
+//!    |   ^^^^^
+//!    = note: Bits of register 'utx_config'
+//! ```
+
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use pest::error::{Error as PestError, ErrorVariant, InputLocation};
+use pest::RuleType;
+
+use crate::rst_table::display_width;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A message about a span of a source file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// Unknown while the text is parsed on its own, callers that read it from a file set it
+    pub file: Option<PathBuf>,
+    /// 1 based
+    pub line: usize,
+    /// 1 based, in characters
+    pub column: usize,
+    /// Display columns marked on the line, at least one. Wide East Asian characters take
+    /// two.
+    pub length: usize,
+    /// The line the span starts on
+    pub snippet: String,
+    /// Grammar rule that failed, for parse errors
+    pub rule: Option<String>,
+}
+
+impl std::error::Error for Diagnostic {}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file.display())?;
+        }
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Diagnostic {
+    /// An error about the bytes `span` of `text`
+    pub fn new(text: &str, span: Range<usize>, message: impl Into<String>) -> Diagnostic {
+        let start = floor_char_boundary(text, span.start.min(text.len()));
+        let end = floor_char_boundary(text, span.end.clamp(start, text.len()));
+        let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+        let snippet = text[line_start..line_end].trim_end_matches('\r');
+        let length = text[start..end.min(line_end)]
+            .chars()
+            .map(display_width)
+            .sum::<usize>();
+
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+            file: None,
+            line: text[..start].matches('\n').count() + 1,
+            column: text[line_start..start].chars().count() + 1,
+            length: length.max(1),
+            // Tabs would shift the marker
+            snippet: snippet.replace('\t', " "),
+            rule: None,
+        }
+    }
+
+    /// The error of a pest parser that failed on `text`
+    pub fn from_pest<R: RuleType>(text: &str, error: PestError<R>) -> Diagnostic {
+        let span = match error.location {
+            InputLocation::Pos(pos) => pos..pos,
+            InputLocation::Span((start, end)) => start..end,
+        };
+        let (message, rule) = match &error.variant {
+            ErrorVariant::ParsingError {
+                positives,
+                negatives,
+            } => {
+                let rules = |rules: &[R]| {
+                    rules
+                        .iter()
+                        .map(|rule| format!("{:?}", rule))
+                        .collect::<Vec<_>>()
+                        .join(" or ")
+                };
+                let found = text[span.start.min(text.len())..]
+                    .chars()
+                    .next()
+                    .map_or("end of file".to_string(), |c| format!("{:?}", c));
+                let message = match (positives.is_empty(), negatives.is_empty()) {
+                    (false, _) => format!("unexpected {}, expected {}", found, rules(positives)),
+                    (true, false) => format!("unexpected {}", rules(negatives)),
+                    (true, true) => format!("unexpected {}", found),
+                };
+                let rule = if positives.is_empty() {
+                    negatives
+                } else {
+                    positives
+                };
+                (message, (!rule.is_empty()).then(|| rules(rule)))
+            }
+            ErrorVariant::CustomError { message } => (message.clone(), None),
+        };
+
+        let mut diagnostic = Diagnostic::new(text, span, message);
+        diagnostic.rule = rule;
+        diagnostic
+    }
+
+    pub fn in_file(self, file: &Path) -> Diagnostic {
+        Diagnostic {
+            file: Some(file.to_path_buf()),
+            ..self
+        }
+    }
+
+    pub fn rule(self, rule: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            rule: Some(rule.into()),
+            ..self
+        }
+    }
+
+    pub fn warning(self) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..self
+        }
+    }
+
+    /// The diagnostic with the source line it points at
+    pub fn render(&self) -> String {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let line = self.line.to_string();
+        let gutter = " ".repeat(line.len());
+        let file = self
+            .file
+            .as_ref()
+            .map_or_else(|| "<input>".to_string(), |file| file.display().to_string());
+        // The marker lines up with the snippet as a terminal shows it
+        let indent: usize = self
+            .snippet
+            .chars()
+            .take(self.column - 1)
+            .map(display_width)
+            .sum();
+
+        let mut out = format!(
+            "{}: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+            severity,
+            self.message,
+            gutter,
+            file,
+            self.line,
+            self.column,
+            gutter,
+            line,
+            self.snippet,
+            gutter,
+            " ".repeat(indent),
+            "^".repeat(self.length)
+        );
+        if let Some(rule) = &self.rule {
+            out.push_str(&format!("\n{} = rule: {}", gutter, rule));
+        }
+        out
+    }
+}
+
+/// Renders `error` like a compiler error if it has a [`Diagnostic`] in its chain, with
+/// the context around it as notes
+pub fn report(error: &anyhow::Error) -> String {
+    let Some(diagnostic) = error.chain().find_map(|e| e.downcast_ref::<Diagnostic>()) else {
+        return format!("error: {:?}", error);
+    };
+
+    let mut out = diagnostic.render();
+    let gutter = " ".repeat(diagnostic.line.to_string().len());
+    for context in error
+        .chain()
+        .take_while(|e| e.downcast_ref::<Diagnostic>().is_none())
+    {
+        out.push_str(&format!("\n{} = note: {}", gutter, context));
+    }
+    out
+}
+
+/// Sets the file of a [`Diagnostic`] `error` that was made without one
+pub(crate) fn in_file(error: anyhow::Error, file: &Path) -> anyhow::Error {
+    match error.downcast::<Diagnostic>() {
+        Ok(diagnostic) if diagnostic.file.is_none() => diagnostic.in_file(file).into(),
+        Ok(diagnostic) => diagnostic.into(),
+        Err(error) => error,
+    }
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    const TEXT: &str = "first line\n| 31-16 | cr_utx_len |\r\nlast";
+
+    #[test]
+    fn position() {
+        let diagnostic = Diagnostic::new(TEXT, 13..18, "Invalid bits: 31-16");
+        assert_eq!((diagnostic.line, diagnostic.column), (2, 3));
+        assert_eq!(diagnostic.length, 5);
+        assert_eq!(diagnostic.snippet, "| 31-16 | cr_utx_len |");
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(diagnostic.to_string(), "2:3: Invalid bits: 31-16");
+        assert_eq!(
+            diagnostic.in_file(Path::new("uart.rst")).to_string(),
+            "uart.rst:2:3: Invalid bits: 31-16"
+        );
+    }
+
+    #[test]
+    fn clamped_spans() {
+        // Spans past the end or across lines mark what's left of their first line
+        let diagnostic = Diagnostic::new(TEXT, 100..200, "at the end");
+        assert_eq!(
+            (diagnostic.line, diagnostic.column, diagnostic.length),
+            (3, 5, 1)
+        );
+        let diagnostic = Diagnostic::new(TEXT, 6..30, "across lines");
+        assert_eq!(
+            (diagnostic.line, diagnostic.column, diagnostic.length),
+            (1, 7, 4)
+        );
+        // Offsets inside a character
+        let diagnostic = Diagnostic::new("位 x", 1..2, "inside");
+        assert_eq!((diagnostic.line, diagnostic.column), (1, 1));
+    }
+
+    #[test]
+    fn render() {
+        let diagnostic = Diagnostic::new(TEXT, 13..18, "Invalid bits: 31-16")
+            .in_file(Path::new("uart.rst"))
+            .rule("GRID_TABLE")
+            .warning();
+        assert_eq!(
+            diagnostic.render(),
+            "warning: Invalid bits: 31-16\n \
+             --> uart.rst:2:3\n  |\n\
+             2 | | 31-16 | cr_utx_len |\n  \
+             |   ^^^^^\n  = rule: GRID_TABLE"
+        );
+    }
+
+    #[test]
+    fn wide_characters() {
+        let text = "| 位 | 使能 | 1'b2 |";
+        let start = text.find("1'b2").unwrap();
+        let diagnostic = Diagnostic::new(text, start..start + 4, "bad digit");
+        // Columns count characters, the marker counts display columns
+        assert_eq!(diagnostic.column, 12);
+        let marker = diagnostic.render().lines().last().unwrap().to_string();
+        assert_eq!(marker, format!("  | {}^^^^", " ".repeat(14)));
+
+        let diagnostic = Diagnostic::new(text, 2..5, "wide");
+        assert_eq!(diagnostic.length, 2);
+    }
+
+    #[test]
+    fn tabs() {
+        let diagnostic = Diagnostic::new("\tx = 1;", 1..2, "tab");
+        assert_eq!(diagnostic.snippet, " x = 1;");
+        assert!(diagnostic.render().ends_with("|  ^"));
+    }
+
+    #[test]
+    fn reports() {
+        let error = anyhow::Error::from(Diagnostic::new(TEXT, 13..18, "Invalid bits"))
+            .context("Bits of register 'utx_config'");
+        let rendered = report(&error);
+        assert!(rendered.starts_with("error: Invalid bits\n --> <input>:2:3"));
+        assert!(rendered.ends_with("\n  = note: Bits of register 'utx_config'"));
+
+        let error: anyhow::Result<()> = Err(anyhow::anyhow!("plain")).context("outer");
+        assert_eq!(
+            report(&error.unwrap_err()),
+            "error: outer\n\nCaused by:\n    plain"
+        );
+    }
+
+    #[test]
+    fn files() {
+        let error = in_file(Diagnostic::new(TEXT, 0..1, "x").into(), Path::new("a.h"));
+        let diagnostic = error.downcast::<Diagnostic>().unwrap();
+        assert_eq!(diagnostic.file.as_deref(), Some(Path::new("a.h")));
+
+        // A file set before stays
+        let error = in_file(diagnostic.into(), Path::new("b.h"));
+        let diagnostic = error.downcast::<Diagnostic>().unwrap();
+        assert_eq!(diagnostic.file.as_deref(), Some(Path::new("a.h")));
+    }
+}
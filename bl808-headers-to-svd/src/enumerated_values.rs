@@ -0,0 +1,184 @@
+use std::collections::HashSet;
+
+use svd_rs::{EnumeratedValue, EnumeratedValues, ValidateLevel};
+
+use crate::literal::parse_literal;
+
+/// Longest generated value name, in words
+const MAX_NAME_WORDS: usize = 4;
+
+/// Builds enumerated values from the description lines of a field that list its values,
+/// like `2'd0 : YUV420 Planar` or `1'b1 - U is even byte`.
+///
+/// Returns `None` if the description doesn't enumerate any values, or if the values it
+/// lists don't fit in `width` bits or are listed more than once.
+pub(crate) fn enumerated_values_from_description(
+    description: &str,
+    width: u32,
+) -> Option<EnumeratedValues> {
+    let mut values = Vec::<EnumeratedValue>::new();
+    let mut seen_values = HashSet::new();
+    let mut seen_names = HashSet::new();
+
+    for (value, text) in description.lines().filter_map(enumeration_line) {
+        if width < 64 && value >> width != 0 {
+            return None;
+        }
+        if !seen_values.insert(value) {
+            return None;
+        }
+
+        let mut name = value_name(text, values.len());
+        if !seen_names.insert(name.clone()) {
+            name = format!("{}_{}", name, value);
+            seen_names.insert(name.clone());
+        }
+
+        values.push(
+            EnumeratedValue::builder()
+                .name(name)
+                .description(Some(text.to_string()))
+                .value(Some(value))
+                .build(ValidateLevel::Weak)
+                .ok()?,
+        );
+    }
+
+    if values.is_empty() {
+        return None;
+    }
+
+    EnumeratedValues::builder()
+        .values(values)
+        .build(ValidateLevel::Weak)
+        .ok()
+}
+
+/// Whether `line` gives a field value, like `2'd0 : YUV420 Planar`
+pub(crate) fn is_enumeration_line(line: &str) -> bool {
+    enumeration_line(line).is_some()
+}
+
+/// Splits `<literal> : text`, `<literal> - text` or `<literal> = text` at the start of a
+/// line into its value and text. The literal is a Verilog literal (`2'd0`), a hex number
+/// (`0x3`) or a decimal. A decimal needs whitespace on both sides of the separator, so prose
+/// like `1-wire mode` or a wrapped `0: disable` isn't taken for a value.
+fn enumeration_line(line: &str) -> Option<(u64, &str)> {
+    let line = line.trim();
+    let separator = line.find([':', '-', '='])?;
+    let (literal, text) = (&line[..separator], &line[separator + 1..]);
+    let spaced = literal.ends_with(char::is_whitespace) && text.starts_with(char::is_whitespace);
+    let (literal, text) = (literal.trim(), text.trim());
+    if literal.is_empty() || text.is_empty() || literal.contains(char::is_whitespace) {
+        return None;
+    }
+    let has_base = literal.contains('\'') || literal.starts_with("0x") || literal.starts_with("0X");
+    if !has_base && !spaced {
+        return None;
+    }
+
+    let literal = parse_literal(literal, 10).ok()?;
+    if literal.dont_care != 0 {
+        return None;
+    }
+
+    Some((literal.value, text))
+}
+
+/// Upper snake case name from the first few words of `text`, e.g. `YUV420_PLANAR`
+fn value_name(text: &str, index: usize) -> String {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .take(MAX_NAME_WORDS)
+        .map(|word| word.to_ascii_uppercase())
+        .collect();
+
+    if words.is_empty() {
+        return format!("VALUE{}", index);
+    }
+
+    let name = words.join("_");
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("V{}", name)
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(description: &str, width: u32) -> Vec<(String, u64)> {
+        enumerated_values_from_description(description, width)
+            .map(|values| {
+                values
+                    .values
+                    .into_iter()
+                    .map(|value| (value.name, value.value.unwrap()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn verilog_values() {
+        let description = "TX mode\n2'd0 : Normal mode\n2'd1 : IR mode\n2'd2 : LIN mode";
+        assert_eq!(
+            values(description, 2),
+            [
+                ("NORMAL_MODE".to_string(), 0),
+                ("IR_MODE".to_string(), 1),
+                ("LIN_MODE".to_string(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn dash_and_hex_values() {
+        let description = "Enable signal of UART TX function\n1'b0 - disabled\n1'b1 - enabled";
+        assert_eq!(
+            values(description, 1),
+            [("DISABLED".to_string(), 0), ("ENABLED".to_string(), 1)]
+        );
+        assert_eq!(
+            values("0x0: off\n0x3: on", 2),
+            [("OFF".to_string(), 0), ("ON".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    fn spaced_decimal_values() {
+        assert_eq!(
+            values("0 : disable\n1 = enable", 1),
+            [("DISABLE".to_string(), 0), ("ENABLE".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn prose_is_not_a_value() {
+        assert!(!is_enumeration_line("1-wire mode select"));
+        assert!(!is_enumeration_line("3-bit counter of the received frames"));
+        assert!(!is_enumeration_line("0: disable, 1: enable"));
+        assert!(!is_enumeration_line(
+            "Write 1 to clear: the interrupt is cleared"
+        ));
+        assert!(!is_enumeration_line("2'd0 :"));
+        assert!(values("Set 1-wire mode\n3-bit counter", 3).is_empty());
+    }
+
+    #[test]
+    fn values_that_do_not_fit() {
+        assert!(values("2'd0 : off\n2'd3 : on", 1).is_empty());
+        assert!(values("1'b0 - off\n1'b0 - also off", 1).is_empty());
+    }
+
+    #[test]
+    fn duplicate_names() {
+        assert_eq!(
+            values("2'd0 : Reserved\n2'd1 : Reserved", 2),
+            [("RESERVED".to_string(), 0), ("RESERVED_1".to_string(), 1)]
+        );
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::c_struct::{select_tokens, tokenize, Token, TokenKind};
+use crate::cpu::Core;
+use crate::diagnostic::{in_file, Diagnostic};
+use crate::literal::parse_literal;
+use crate::peripheral_from_c_defines::parse_define;
+
+/// An interrupt number from the SDK, `UART0_IRQn = IRQ_NUM_BASE + 28`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IrqDefinition {
+    /// Name without the `_IRQn` suffix, `UART0`
+    pub name: String,
+    pub value: u32,
+    /// Comment documenting the interrupt, if any
+    pub description: Option<String>,
+}
+
+/// Reads the `*_IRQn` enumerators of the `IRQn_Type` enums and the `*_IRQn` defines of
+/// `files` as `core` sees them, skipping the `#if defined(CPU_..)` blocks of the other
+/// cores. Values can refer to other defines and enumerators, like `IRQ_NUM_BASE + 28`.
+///
+/// An interrupt defined more than once keeps its first value, conflicting definitions
+/// are reported. Enumerators whose value can't be evaluated are reported and skipped,
+/// along with the ones counting on from them.
+pub fn irqs_from_headers(files: &[PathBuf], core: Core) -> Result<Vec<IrqDefinition>> {
+    let mut irqs = Vec::<IrqDefinition>::new();
+
+    for file in files {
+        let header = fs::read_to_string(file)
+            .with_context(|| format!("Error reading file: {}", file.display()))?;
+
+        for irq in parse_irqs(file, &header, core).map_err(|e| in_file(e, file))? {
+            match irqs.iter().find(|known| known.name == irq.name) {
+                Some(known) if known.value != irq.value => println!(
+                    "Warning: {}: {} interrupt {} is {} but was defined as {} before, keeping {}",
+                    file.display(),
+                    core.name(),
+                    irq.name,
+                    irq.value,
+                    known.value,
+                    known.value
+                ),
+                Some(_) => {}
+                None => irqs.push(irq),
+            }
+        }
+    }
+
+    Ok(irqs)
+}
+
+/// Interrupts of the header `file` whose text is `header`, as `core` sees them
+fn parse_irqs(file: &Path, header: &str, core: Core) -> Result<Vec<IrqDefinition>> {
+    let core_defines = Core::ALL.map(|core| core.define());
+    let tokens = tokenize(header)?;
+    let tokens = select_tokens(&tokens, &core_defines, &[core.define()]);
+
+    let parser = IrqParser {
+        file,
+        text: header,
+        symbols: HashMap::new(),
+    };
+    Ok(parser.parse(&tokens))
+}
+
+struct IrqParser<'a> {
+    file: &'a Path,
+    /// The header the tokens are read from
+    text: &'a str,
+    /// Values of the defines and enumerators seen so far
+    symbols: HashMap<&'a str, i64>,
+}
+
+impl<'a> IrqParser<'a> {
+    fn parse(mut self, tokens: &[Token<'a>]) -> Vec<IrqDefinition> {
+        let mut irqs = Vec::new();
+        let mut pos = 0;
+
+        while pos < tokens.len() {
+            let token = tokens[pos];
+            pos += 1;
+
+            if token.kind == TokenKind::Preprocessor {
+                let Some((name, value)) = parse_define(token.text) else {
+                    continue;
+                };
+                let Some(value) = self.evaluate(value) else {
+                    continue;
+                };
+                self.symbols.insert(name, value);
+                if let Some(irq) = irq_definition(name, value, None) {
+                    irqs.push(irq);
+                }
+            } else if token.is("enum") {
+                pos = self.enumerators(tokens, pos, &mut irqs);
+            }
+        }
+
+        irqs
+    }
+
+    /// Reads the enumerators of an enum whose keyword is before `pos`, returns the
+    /// position after its closing brace
+    fn enumerators(
+        &mut self,
+        tokens: &[Token<'a>],
+        mut pos: usize,
+        irqs: &mut Vec<IrqDefinition>,
+    ) -> usize {
+        let code = |pos: usize| {
+            tokens[pos..]
+                .iter()
+                .position(|t| !matches!(t.kind, TokenKind::Comment | TokenKind::Preprocessor))
+                .map(|i| pos + i)
+        };
+
+        // Optional tag, then the enumerator list
+        let Some(mut brace) = code(pos) else {
+            return tokens.len();
+        };
+        if tokens[brace].kind == TokenKind::Identifier {
+            match code(brace + 1) {
+                Some(next) => brace = next,
+                None => return tokens.len(),
+            }
+        }
+        if !tokens[brace].is("{") {
+            return pos;
+        }
+        pos = brace + 1;
+
+        // Unknown after an enumerator that couldn't be evaluated, counting on from it would
+        // shift every later number
+        let mut next_value = Some(0);
+        while let Some(start) = code(pos) {
+            if tokens[start].is("}") {
+                return start + 1;
+            }
+            let name = tokens[start].text;
+            pos = start + 1;
+
+            // Expression up to the next ',' or '}'
+            let mut value = next_value;
+            if code(pos).is_some_and(|i| tokens[i].is("=")) {
+                let start = code(pos).unwrap() + 1;
+                let end = tokens[start..]
+                    .iter()
+                    .position(|t| t.is(",") || t.is("}"))
+                    .map_or(tokens.len(), |i| start + i);
+                let expression: Vec<&str> = tokens[start..end]
+                    .iter()
+                    .filter(|t| t.kind != TokenKind::Comment)
+                    .map(|t| t.text)
+                    .collect();
+                value = self.evaluate(&expression.join(" "));
+                if value.is_none() {
+                    let message = format!(
+                        "Can't evaluate the value of {}, skipping it and the enumerators after it without a value",
+                        name
+                    );
+                    let warning = Diagnostic::new(self.text, tokens[start].span(), message)
+                        .in_file(self.file)
+                        .warning();
+                    println!("{}", warning.render());
+                }
+                pos = end;
+            }
+            next_value = value.map(|value| value + 1);
+            if let Some(value) = value {
+                self.symbols.insert(name, value);
+            }
+
+            if let Some(next) = code(pos) {
+                if tokens[next].is(",") {
+                    pos = next + 1;
+                }
+            }
+            // The comment after the enumerator documents it
+            let description = tokens
+                .get(pos)
+                .filter(|t| t.kind == TokenKind::Comment && t.line == tokens[start].line)
+                .map(|t| t.comment_text().trim_start_matches("!<").trim().to_string());
+            if let Some(irq) = value.and_then(|value| irq_definition(name, value, description)) {
+                irqs.push(irq);
+            }
+        }
+
+        tokens.len()
+    }
+
+    /// Evaluates sums like `(IRQ_NUM_BASE + 28)` of literals and known symbols
+    fn evaluate(&self, expression: &str) -> Option<i64> {
+        let expression = expression.replace(['(', ')'], " ");
+        let mut total: i64 = 0;
+        let mut sign = 1;
+        let mut expect_term = true;
+
+        for word in expression
+            .split_inclusive(['+', '-'])
+            .flat_map(|part| match part.strip_suffix(['+', '-']) {
+                Some(term) => vec![term.trim(), &part[part.len() - 1..]],
+                None => vec![part.trim()],
+            })
+            .filter(|word| !word.is_empty())
+        {
+            match word {
+                "+" | "-" if !expect_term => {
+                    sign = if word == "-" { -1 } else { 1 };
+                    expect_term = true;
+                }
+                _ if expect_term => {
+                    let value = match self.symbols.get(word) {
+                        Some(value) => *value,
+                        None => parse_literal(word, 10).ok()?.value as i64,
+                    };
+                    total += sign * value;
+                    expect_term = false;
+                }
+                _ => return None,
+            }
+        }
+
+        (!expect_term).then_some(total)
+    }
+}
+
+fn irq_definition(name: &str, value: i64, description: Option<String>) -> Option<IrqDefinition> {
+    let name = name.strip_suffix("_IRQn")?;
+    Some(IrqDefinition {
+        name: name.to_string(),
+        value: u32::try_from(value).ok()?,
+        description: description.filter(|d| !d.is_empty()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BL808_H: &str = "#define IRQ_NUM_BASE 16
+typedef enum {
+#if defined(CPU_M0) || defined(CPU_LP)
+    /******  RISC-V Processor Exceptions Numbers *******/
+    MSOFT_IRQn = 3,           /*!< 3 RISCV machine software Interrupt */
+    MTIME_IRQn = 7,           /*!< 7 RISCV machine time Interrupt */
+    /******  BL808 specific Interrupt Numbers **********/
+    BMX_MCU_BUS_ERR_IRQn = IRQ_NUM_BASE + 0, /*!< bmx mcu bus_err_int */
+    BMX_MCU_TO_IRQn,
+    UART0_IRQn = IRQ_NUM_BASE + 28,  /*!< uart0 Interrupt */
+    USB_IRQn = (IRQ_NUM_BASE + 39),
+    GLB_IRQn,
+#endif
+#if defined(CPU_D0)
+    UART3_IRQn = IRQ_NUM_BASE + 4,
+    UART0_IRQn = IRQ_NUM_BASE + 30,
+#endif
+    IRQn_LAST,
+} IRQn_Type;
+#define IPC_M0_IRQn (IRQ_NUM_BASE + 54)
+";
+
+    fn irqs(header: &str, core: Core) -> Vec<(String, u32)> {
+        parse_irqs(Path::new("bl808.h"), header, core)
+            .unwrap()
+            .into_iter()
+            .map(|irq| (irq.name, irq.value))
+            .collect()
+    }
+
+    fn irq(name: &str, value: u32) -> (String, u32) {
+        (name.to_string(), value)
+    }
+
+    #[test]
+    fn enumerators_and_defines() {
+        assert_eq!(
+            irqs(BL808_H, Core::M0),
+            [
+                irq("MSOFT", 3),
+                irq("MTIME", 7),
+                irq("BMX_MCU_BUS_ERR", 16),
+                irq("BMX_MCU_TO", 17),
+                irq("UART0", 44),
+                irq("USB", 55),
+                irq("GLB", 56),
+                irq("IPC_M0", 70),
+            ]
+        );
+    }
+
+    #[test]
+    fn other_cores_blocks_are_skipped() {
+        assert_eq!(
+            irqs(BL808_H, Core::D0),
+            [irq("UART3", 20), irq("UART0", 46), irq("IPC_M0", 70)]
+        );
+    }
+
+    #[test]
+    fn descriptions() {
+        let irqs = parse_irqs(Path::new("bl808.h"), BL808_H, Core::M0).unwrap();
+        assert_eq!(
+            irqs[0].description.as_deref(),
+            Some("3 RISCV machine software Interrupt")
+        );
+        assert_eq!(irqs[3].description, None);
+    }
+
+    #[test]
+    fn unknown_values_stop_the_count() {
+        let header = "enum {
+    A_IRQn = 1,
+    B_IRQn = SOMETHING_UNKNOWN + 1,
+    C_IRQn,
+    D_IRQn = 10,
+    E_IRQn,
+};";
+        assert_eq!(
+            irqs(header, Core::M0),
+            [irq("A", 1), irq("D", 10), irq("E", 11)]
+        );
+    }
+
+    #[test]
+    fn expressions() {
+        let header = "#define BASE 0x10
+#define ONE (BASE - 15)
+#define X_IRQn (BASE + ONE + 2U)
+#define Y_IRQn (BASE * 2)
+#define Z_IRQn (ONE - 2)";
+        assert_eq!(irqs(header, Core::LP), [irq("X", 19)]);
+    }
+}
@@ -0,0 +1,40 @@
+//! Assembles an SVD description of the Bouffalo Lab BL808 from the register headers in the
+//! vendor SDKs and the register chapters of the reference manual.
+
+extern crate pest;
+
+#[macro_use]
+extern crate pest_derive;
+
+pub mod access;
+mod c_struct;
+pub mod cpu;
+pub mod device;
+pub mod diagnostic;
+mod enumerated_values;
+pub mod interrupts;
+pub mod literal;
+pub mod manifest;
+pub mod memory_map;
+pub mod peripheral_from_c_defines;
+pub mod peripheral_from_c_header;
+pub mod peripheral_from_doc_rst;
+pub mod register_source;
+pub mod register_usage;
+mod reset_value;
+pub mod rst_markup;
+mod rst_table;
+pub mod source_roots;
+pub mod vendor_extensions;
+
+pub use cpu::{Core, CpuProperties};
+pub use device::{DeviceAssembler, DeviceMetadata};
+pub use interrupts::IrqDefinition;
+pub use manifest::Manifest;
+pub use memory_map::MemoryMap;
+pub use peripheral_from_c_defines::CDefinesSource;
+pub use peripheral_from_c_header::CHeaderSource;
+pub use peripheral_from_doc_rst::DocRstSource;
+pub use register_source::{RegisterBlock, RegisterSource, Translation};
+pub use rst_markup::DescriptionFormat;
+pub use source_roots::SourceRoots;
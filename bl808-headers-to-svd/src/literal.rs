@@ -0,0 +1,399 @@
+//! Numeric literals as they appear in the SDK headers and the reference manual: C style
+//! (`0x1F`, `12`, `0x0L`, `8UL`) and Verilog style (`1'b0`, `8'hFF`, `2'd3`, `4'bxx01`).
+
+use std::ops::Range;
+
+use thiserror::Error;
+
+/// A parsed numeric literal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Literal {
+    pub value: u64,
+    /// Width declared by a Verilog literal, `8` for `8'hFF`
+    pub width: Option<u32>,
+    /// Bits given as `x`, `z` or `?` digits. They read as zero in `value`.
+    pub dont_care: u64,
+}
+
+/// Why a literal didn't parse. Spans are byte ranges into the parsed text, use
+/// [`LiteralError::offset`] to make them relative to a larger source.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LiteralError {
+    #[error("empty literal")]
+    Empty { span: Range<usize> },
+    #[error("unknown literal base '{base}'")]
+    UnknownBase { base: char, span: Range<usize> },
+    #[error("invalid literal width")]
+    InvalidWidth { span: Range<usize> },
+    #[error("invalid digit '{digit}' for base {radix}")]
+    InvalidDigit {
+        digit: char,
+        radix: u32,
+        span: Range<usize>,
+    },
+    #[error("literal doesn't fit in 64 bits")]
+    Overflow { span: Range<usize> },
+    #[error("value needs {needed} bits but the literal is declared {width} bits wide")]
+    WidthMismatch {
+        width: u32,
+        needed: u32,
+        span: Range<usize>,
+    },
+}
+
+impl LiteralError {
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            LiteralError::Empty { span }
+            | LiteralError::UnknownBase { span, .. }
+            | LiteralError::InvalidWidth { span }
+            | LiteralError::InvalidDigit { span, .. }
+            | LiteralError::Overflow { span }
+            | LiteralError::WidthMismatch { span, .. } => span.clone(),
+        }
+    }
+
+    /// Moves the span by `by` bytes, for literals cut out of a larger text
+    pub fn offset(mut self, by: usize) -> LiteralError {
+        match &mut self {
+            LiteralError::Empty { span }
+            | LiteralError::UnknownBase { span, .. }
+            | LiteralError::InvalidWidth { span }
+            | LiteralError::InvalidDigit { span, .. }
+            | LiteralError::Overflow { span }
+            | LiteralError::WidthMismatch { span, .. } => {
+                *span = span.start + by..span.end + by;
+            }
+        }
+        self
+    }
+}
+
+impl Literal {
+    /// Whether the literal fits in a field `width` bits wide
+    pub fn fits(&self, width: u32) -> bool {
+        bits_needed(self.value | self.dont_care) <= width
+    }
+}
+
+/// Parses a C or Verilog literal. Numbers without a prefix or base use `default_radix`.
+pub fn parse_literal(text: &str, default_radix: u32) -> Result<Literal, LiteralError> {
+    let start = text.len() - text.trim_start().len();
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return Err(LiteralError::Empty {
+            span: 0..text.len(),
+        });
+    }
+
+    if let Some(apostrophe) = trimmed.find('\'') {
+        return parse_verilog(trimmed, apostrophe).map_err(|e| e.offset(start));
+    }
+
+    // C integer suffixes
+    let digits = trimmed.trim_end_matches(['u', 'U', 'l', 'L']);
+    let (radix, digits_start) = if digits.starts_with("0x") || digits.starts_with("0X") {
+        (16, 2)
+    } else if default_radix != 16 && (digits.starts_with("0b") || digits.starts_with("0B")) {
+        (2, 2)
+    } else {
+        (default_radix, 0)
+    };
+    let (value, dont_care) = parse_digits(&digits[digits_start..], radix, start + digits_start)?;
+
+    Ok(Literal {
+        value,
+        width: None,
+        dont_care,
+    })
+}
+
+fn parse_verilog(text: &str, apostrophe: usize) -> Result<Literal, LiteralError> {
+    let width_text = text[..apostrophe].replace('_', "");
+    let width = if width_text.is_empty() {
+        None
+    } else {
+        match width_text.parse::<u32>() {
+            Ok(width) if width > 0 => Some(width),
+            _ => {
+                return Err(LiteralError::InvalidWidth {
+                    span: 0..apostrophe,
+                })
+            }
+        }
+    };
+
+    let mut rest = &text[apostrophe + 1..];
+    let mut base_start = apostrophe + 1;
+    // Signed literals, 8'sh80
+    if rest.starts_with(['s', 'S']) {
+        rest = &rest[1..];
+        base_start += 1;
+    }
+    let base = rest.chars().next().ok_or(LiteralError::UnknownBase {
+        base: ' ',
+        span: base_start..text.len(),
+    })?;
+    let radix = match base.to_ascii_lowercase() {
+        'b' => 2,
+        'o' => 8,
+        'd' => 10,
+        'h' => 16,
+        _ => {
+            return Err(LiteralError::UnknownBase {
+                base,
+                span: base_start..base_start + base.len_utf8(),
+            })
+        }
+    };
+
+    let digits_start = base_start + 1;
+    let digits = text[digits_start..].trim_start();
+    let digits_start = text.len() - digits.len();
+    if digits.is_empty() {
+        return Err(LiteralError::Empty {
+            span: 0..text.len(),
+        });
+    }
+
+    let (value, dont_care) = if radix == 10 && is_dont_care_only(digits) {
+        // 4'dx, every bit is unknown
+        (0, width.map_or(u64::MAX, low_mask))
+    } else {
+        parse_digits(digits, radix, digits_start)?
+    };
+
+    if let Some(width) = width {
+        let needed = bits_needed(value | dont_care);
+        if needed > width {
+            return Err(LiteralError::WidthMismatch {
+                width,
+                needed,
+                span: 0..text.len(),
+            });
+        }
+    }
+
+    Ok(Literal {
+        value,
+        width,
+        dont_care,
+    })
+}
+
+/// Parses `digits` in `radix`, skipping `_` separators. `x`, `z` and `?` digits are
+/// collected as don't care bits. `offset` is where `digits` starts in the literal.
+fn parse_digits(digits: &str, radix: u32, offset: usize) -> Result<(u64, u64), LiteralError> {
+    let bits_per_digit = match radix {
+        2 => Some(1),
+        8 => Some(3),
+        16 => Some(4),
+        _ => None,
+    };
+
+    let mut value: u64 = 0;
+    let mut dont_care: u64 = 0;
+    let mut any_digit = false;
+    for (i, c) in digits.char_indices() {
+        let span = offset + i..offset + i + c.len_utf8();
+        if c == '_' {
+            continue;
+        }
+        any_digit = true;
+
+        if matches!(c, 'x' | 'X' | 'z' | 'Z' | '?') {
+            let bits = bits_per_digit.ok_or(LiteralError::InvalidDigit {
+                digit: c,
+                radix,
+                span: span.clone(),
+            })?;
+            if value.leading_zeros() < bits || dont_care.leading_zeros() < bits {
+                return Err(LiteralError::Overflow { span });
+            }
+            value <<= bits;
+            dont_care = (dont_care << bits) | low_mask(bits);
+            continue;
+        }
+
+        let digit = c.to_digit(radix).ok_or(LiteralError::InvalidDigit {
+            digit: c,
+            radix,
+            span: span.clone(),
+        })?;
+        value = value
+            .checked_mul(radix as u64)
+            .and_then(|v| v.checked_add(digit as u64))
+            .ok_or(LiteralError::Overflow { span: span.clone() })?;
+        if let Some(bits) = bits_per_digit {
+            if dont_care.leading_zeros() < bits {
+                return Err(LiteralError::Overflow { span });
+            }
+            dont_care <<= bits;
+        }
+    }
+
+    if !any_digit {
+        return Err(LiteralError::Empty {
+            span: offset..offset + digits.len(),
+        });
+    }
+
+    Ok((value, dont_care))
+}
+
+fn is_dont_care_only(digits: &str) -> bool {
+    digits
+        .chars()
+        .all(|c| matches!(c, 'x' | 'X' | 'z' | 'Z' | '?' | '_'))
+}
+
+fn bits_needed(value: u64) -> u32 {
+    64 - value.leading_zeros()
+}
+
+fn low_mask(bits: u32) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1u64 << bits) - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(text: &str) -> u64 {
+        parse_literal(text, 10).unwrap().value
+    }
+
+    #[test]
+    fn c_literals() {
+        assert_eq!(value("12"), 12);
+        assert_eq!(value("0x1F"), 0x1f);
+        assert_eq!(value("0X1f"), 0x1f);
+        assert_eq!(value("0b101"), 5);
+        assert_eq!(parse_literal("12", 10).unwrap().width, None);
+    }
+
+    #[test]
+    fn c_suffixes() {
+        assert_eq!(value("0x0L"), 0);
+        assert_eq!(value("8UL"), 8);
+        assert_eq!(value("0xFFu"), 0xff);
+        assert_eq!(value("1ull"), 1);
+    }
+
+    #[test]
+    fn default_radix() {
+        assert_eq!(parse_literal("10", 16).unwrap().value, 0x10);
+        // 0b would be hex digits
+        assert_eq!(parse_literal("0b1", 16).unwrap().value, 0xb1);
+    }
+
+    #[test]
+    fn verilog_literals() {
+        let literal = parse_literal("8'hFF", 10).unwrap();
+        assert_eq!(literal.value, 0xff);
+        assert_eq!(literal.width, Some(8));
+        assert_eq!(value("1'b0"), 0);
+        assert_eq!(value("2'd3"), 3);
+        assert_eq!(value("6'o17"), 0o17);
+        assert_eq!(value("16'h00_FF"), 0xff);
+        assert_eq!(value("8'sh80"), 0x80);
+        assert_eq!(value("'h10"), 0x10);
+        assert_eq!(parse_literal("'h10", 10).unwrap().width, None);
+    }
+
+    #[test]
+    fn dont_care_digits() {
+        let literal = parse_literal("4'bxx01", 10).unwrap();
+        assert_eq!(literal.value, 0b0001);
+        assert_eq!(literal.dont_care, 0b1100);
+
+        let literal = parse_literal("8'hz?", 10).unwrap();
+        assert_eq!(literal.value, 0);
+        assert_eq!(literal.dont_care, 0xff);
+
+        let literal = parse_literal("8'h?1", 10).unwrap();
+        assert_eq!(literal.value, 0x01);
+        assert_eq!(literal.dont_care, 0xf0);
+
+        let literal = parse_literal("4'dx", 10).unwrap();
+        assert_eq!(literal.value, 0);
+        assert_eq!(literal.dont_care, 0xf);
+    }
+
+    #[test]
+    fn fits() {
+        assert!(parse_literal("8'hFF", 10).unwrap().fits(8));
+        assert!(!parse_literal("0x100", 10).unwrap().fits(8));
+        assert!(!parse_literal("4'bx000", 10).unwrap().fits(3));
+    }
+
+    #[test]
+    fn width_mismatch() {
+        assert_eq!(
+            parse_literal("2'd4", 10),
+            Err(LiteralError::WidthMismatch {
+                width: 2,
+                needed: 3,
+                span: 0..4
+            })
+        );
+    }
+
+    #[test]
+    fn error_spans() {
+        assert_eq!(
+            parse_literal("5'h1g", 10),
+            Err(LiteralError::InvalidDigit {
+                digit: 'g',
+                radix: 16,
+                span: 4..5
+            })
+        );
+        assert_eq!(
+            parse_literal("  0x1g", 10),
+            Err(LiteralError::InvalidDigit {
+                digit: 'g',
+                radix: 16,
+                span: 5..6
+            })
+        );
+        assert_eq!(
+            parse_literal(" 4'q0", 10),
+            Err(LiteralError::UnknownBase {
+                base: 'q',
+                span: 3..4
+            })
+        );
+        assert_eq!(
+            parse_literal("0'b0", 10),
+            Err(LiteralError::InvalidWidth { span: 0..1 })
+        );
+        assert_eq!(
+            parse_literal("8'dx1", 10),
+            Err(LiteralError::InvalidDigit {
+                digit: 'x',
+                radix: 10,
+                span: 3..4
+            })
+        );
+        assert_eq!(
+            parse_literal("   ", 10),
+            Err(LiteralError::Empty { span: 0..3 })
+        );
+        assert_eq!(
+            parse_literal("0x1_0000_0000_0000_0000", 10),
+            Err(LiteralError::Overflow { span: 22..23 })
+        );
+    }
+
+    #[test]
+    fn offset() {
+        let error = parse_literal("5'h1g", 10).unwrap_err().offset(20);
+        assert_eq!(error.span(), 24..25);
+        assert_eq!(error.to_string(), "invalid digit 'g' for base 16");
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::cpu::{Core, CpuProperties};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// Group names, each with the name prefixes of the peripherals it holds
+    #[serde(default)]
+    pub groups: BTreeMap<String, Vec<String>>,
+    /// `<cpu>` properties of each core, cores without any get no `<cpu>`
+    #[serde(default)]
+    pub cpu: CoreCpus,
+    #[serde(rename = "peripheral", default)]
+    pub peripherals: Vec<PeripheralEntry>,
+}
+
+/// The `[cpu.M0]`, `[cpu.D0]` and `[cpu.LP]` tables
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CoreCpus {
+    #[serde(rename = "M0")]
+    pub m0: Option<CpuProperties>,
+    #[serde(rename = "D0")]
+    pub d0: Option<CpuProperties>,
+    #[serde(rename = "LP")]
+    pub lp: Option<CpuProperties>,
+}
+
+impl CoreCpus {
+    pub fn get(&self, core: Core) -> Option<&CpuProperties> {
+        match core {
+            Core::M0 => self.m0.as_ref(),
+            Core::D0 => self.d0.as_ref(),
+            Core::LP => self.lp.as_ref(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PeripheralEntry {
+    pub name: String,
+    pub source: SourceKind,
+    pub file: String,
+    /// Overrides the `*_BASE` define, which has to agree with it if there is one
+    pub base_address: Option<u64>,
+    /// Memory map define of the base address, `<name>_BASE` by default
+    pub base_define: Option<String>,
+    /// The memory map has no define for the peripheral, `base_address` can't be checked
+    #[serde(default)]
+    pub no_base_define: bool,
+    #[serde(default)]
+    pub language: Language,
+    /// Cores that can reach the peripheral, all of them if empty
+    #[serde(default)]
+    pub cores: Vec<Core>,
+    /// Files of the same source kind whose registers are merged into this peripheral
+    #[serde(default)]
+    pub append: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    Header,
+    /// A bl_mcu_sdk header describing registers with `_OFFSET`/`_POS`/`_LEN` defines
+    Defines,
+    Rst,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum Language {
+    #[default]
+    #[serde(rename = "en")]
+    En,
+    #[serde(rename = "zh_CN")]
+    ZhCn,
+}
+
+impl Language {
+    /// Name of the language's bl_docs tree
+    pub fn code(&self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::ZhCn => "zh_CN",
+        }
+    }
+
+    /// BCP 47 tag of the language, for `xml:lang`
+    pub fn xml_lang(&self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::ZhCn => "zh-CN",
+        }
+    }
+
+    pub fn other(&self) -> Language {
+        match self {
+            Language::En => Language::ZhCn,
+            Language::ZhCn => Language::En,
+        }
+    }
+}
+
+impl Manifest {
+    pub fn from_file(file: &Path) -> Result<Manifest> {
+        let manifest_string = fs::read_to_string(file)
+            .with_context(|| format!("Error reading manifest: {}", file.display()))?;
+        let manifest: Manifest = toml::from_str(&manifest_string)
+            .with_context(|| format!("Error parsing manifest: {}", file.display()))?;
+
+        for entry in &manifest.peripherals {
+            entry
+                .check()
+                .with_context(|| format!("In manifest: {}", file.display()))?;
+        }
+
+        Ok(manifest)
+    }
+
+    /// SDK macro prefixes of the peripherals named differently in the SVD, taken from
+    /// their `base_define`: `HBN` for LowPower at `HBN_BASE`
+    pub fn macro_prefixes(&self) -> HashMap<String, String> {
+        self.peripherals
+            .iter()
+            .filter_map(|entry| {
+                let prefix = entry.base_define.as_deref()?.strip_suffix("_BASE")?;
+                Some((entry.name.clone(), prefix.to_string()))
+            })
+            .collect()
+    }
+
+    /// Group of the peripheral `name`, the group with the longest prefix of it
+    pub fn group_name(&self, name: &str) -> Option<&str> {
+        self.groups
+            .iter()
+            .flat_map(|(group, prefixes)| prefixes.iter().map(move |prefix| (group, prefix)))
+            .filter(|(_, prefix)| name.starts_with(prefix.as_str()))
+            .max_by_key(|(_, prefix)| prefix.len())
+            .map(|(group, _)| group.as_str())
+    }
+}
+
+impl PeripheralEntry {
+    /// Name of the memory map define of the base address
+    pub fn base_define(&self) -> String {
+        self.base_define
+            .clone()
+            .unwrap_or_else(|| format!("{}_BASE", self.name))
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.no_base_define && (self.base_address.is_none() || self.base_define.is_some()) {
+            return Err(anyhow!(
+                "Peripheral {}: no_base_define needs a base_address and no base_define",
+                self.name
+            ));
+        }
+        if self.source == SourceKind::Rst && !self.append.is_empty() {
+            return Err(anyhow!(
+                "Peripheral {}: append is only supported for header and defines sources",
+                self.name
+            ));
+        }
+
+        Ok(())
+    }
+}
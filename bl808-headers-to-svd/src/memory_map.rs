@@ -0,0 +1,147 @@
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::c_struct::{tokenize, TokenKind};
+use crate::diagnostic::in_file;
+use crate::peripheral_from_c_defines::{define_value, parse_define};
+
+/// Peripheral base addresses from the `*_BASE` defines of the SDK, e.g.
+/// `#define GLB_BASE ((uint32_t)0x20000000)`
+#[derive(Debug, Clone, Default)]
+pub struct MemoryMap {
+    bases: BTreeMap<String, (u64, PathBuf)>,
+}
+
+impl MemoryMap {
+    /// Reads the defines of every header in `files`. When headers disagree the first one
+    /// wins, with a warning.
+    pub fn from_headers(files: &[PathBuf]) -> Result<MemoryMap> {
+        let mut memory_map = MemoryMap::default();
+
+        for file in files {
+            let header = fs::read_to_string(file)
+                .with_context(|| format!("Error reading file: {}", file.display()))?;
+            let tokens = tokenize(&header).map_err(|e| in_file(e, file))?;
+
+            for token in tokens {
+                if token.kind != TokenKind::Preprocessor {
+                    continue;
+                }
+                let Some((name, value)) = parse_define(token.text) else {
+                    continue;
+                };
+                // Defines built from other defines are skipped
+                if !name.ends_with("_BASE") {
+                    continue;
+                }
+                let Some(address) = define_value(value) else {
+                    continue;
+                };
+
+                match memory_map.bases.get(name) {
+                    Some((known, known_file)) if *known != address => println!(
+                        "Warning: {}: {} is 0x{:x} but {} defines it as 0x{:x}, using the latter",
+                        file.display(),
+                        name,
+                        address,
+                        known_file.display(),
+                        known
+                    ),
+                    Some(_) => {}
+                    None => {
+                        memory_map
+                            .bases
+                            .insert(name.to_string(), (address, file.clone()));
+                    }
+                }
+            }
+        }
+
+        Ok(memory_map)
+    }
+
+    /// Address of the `define`, e.g. `GLB_BASE`
+    pub fn base_address(&self, define: &str) -> Option<u64> {
+        self.bases.get(define).map(|(address, _)| *address)
+    }
+
+    /// Checks a hand-written address against the define, and falls back to the define
+    /// if there isn't one. A hand-written address is only expected for peripherals the
+    /// memory map has no define for, `undefined`; otherwise it's a warning.
+    pub fn resolve(
+        &self,
+        define: &str,
+        manual: Option<u64>,
+        undefined: bool,
+    ) -> Result<Option<u64>> {
+        match (manual, self.bases.get(define)) {
+            (Some(manual), Some((defined, file))) if manual != *defined => Err(anyhow!(
+                "Base address 0x{:x} disagrees with {} = 0x{:x} in {}",
+                manual,
+                define,
+                defined,
+                file.display()
+            )),
+            (Some(manual), Some((_, file))) => {
+                println!(
+                    "Warning: base address 0x{:x} repeats {} of {}, drop it from the manifest",
+                    manual,
+                    define,
+                    file.display()
+                );
+                Ok(Some(manual))
+            }
+            (Some(manual), None) => {
+                // Without a memory map nothing can be checked, that's reported once
+                if !undefined && !self.bases.is_empty() {
+                    println!(
+                        "Warning: {} isn't in the memory map, base address 0x{:x} is unchecked. Fix base_define, or set no_base_define if the SDK has no define for it",
+                        define, manual
+                    );
+                }
+                Ok(Some(manual))
+            }
+            (None, defined) => Ok(defined.map(|(address, _)| *address)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory_map() -> MemoryMap {
+        MemoryMap {
+            bases: BTreeMap::from([(
+                "USB_BASE".to_string(),
+                (0x2007_2000, PathBuf::from("bl808.h")),
+            )]),
+        }
+    }
+
+    #[test]
+    fn resolve() {
+        let memory_map = memory_map();
+        assert_eq!(
+            memory_map.resolve("USB_BASE", None, false).unwrap(),
+            Some(0x2007_2000)
+        );
+        assert_eq!(
+            memory_map
+                .resolve("USB_BASE", Some(0x2007_2000), false)
+                .unwrap(),
+            Some(0x2007_2000)
+        );
+        assert!(memory_map
+            .resolve("USB_BASE", Some(0x200_7200), false)
+            .is_err());
+        assert_eq!(memory_map.resolve("OSD_A_BASE", None, false).unwrap(), None);
+        assert_eq!(
+            memory_map
+                .resolve("OSD_A_BASE", Some(0x3001_3000), true)
+                .unwrap(),
+            Some(0x3001_3000)
+        );
+    }
+}
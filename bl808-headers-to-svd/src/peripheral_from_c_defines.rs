@@ -0,0 +1,413 @@
+use std::{
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use svd_rs::{BitRange, Field, FieldInfo, Register, RegisterCluster, RegisterInfo, ValidateLevel};
+
+use crate::c_struct::{tokenize, TokenKind};
+use crate::diagnostic::{in_file, Diagnostic};
+use crate::literal::parse_literal;
+use crate::peripheral_from_c_header::{parse_register_header, DEFAULT_REGISTER_SIZE};
+use crate::register_source::{RegisterBlock, RegisterSource};
+
+/// Register header of the bl_mcu_sdk, which describes each register with macro families
+/// instead of a struct:
+///
+/// ```c
+/// /* 0x0 : soc_info0 */
+/// #define GLB_SOC_INFO0_OFFSET (0x0)
+/// #define GLB_CHIP_RDY_POS     (27U)
+/// #define GLB_CHIP_RDY_LEN     (1U)
+/// #define GLB_CHIP_RDY_MSK     (((1U<<GLB_CHIP_RDY_LEN)-1)<<GLB_CHIP_RDY_POS)
+/// #define GLB_CHIP_RDY_UMSK    (~(((1U<<GLB_CHIP_RDY_LEN)-1)<<GLB_CHIP_RDY_POS))
+/// ```
+///
+/// Fields belong to the `_OFFSET` define before them. `_MSK` and `_UMSK` are derived
+/// from `_POS` and `_LEN`, so only those two are read. The macros don't document access
+/// or reset values.
+#[derive(Debug, Clone)]
+pub struct CDefinesSource {
+    pub path: PathBuf,
+}
+
+impl CDefinesSource {
+    pub fn new(path: impl Into<PathBuf>) -> CDefinesSource {
+        CDefinesSource { path: path.into() }
+    }
+}
+
+impl RegisterSource for CDefinesSource {
+    fn load(&self) -> Result<RegisterBlock> {
+        Ok(RegisterBlock {
+            base_address: None,
+            description: None,
+            registers: registers_from_c_defines(&self.path)?,
+            translations: Vec::new(),
+        })
+    }
+}
+
+#[derive(Default)]
+struct DefinedRegister {
+    name: String,
+    offset: u32,
+    fields: Vec<DefinedField>,
+}
+
+struct DefinedField {
+    /// Macro name without `_POS`/`_LEN`
+    macro_name: String,
+    position: Option<u32>,
+    width: Option<u32>,
+    /// Bytes of the first define of the field
+    span: Range<usize>,
+}
+
+impl DefinedRegister {
+    fn field(&mut self, macro_name: &str, span: Range<usize>) -> &mut DefinedField {
+        match self
+            .fields
+            .iter()
+            .position(|field| field.macro_name == macro_name)
+        {
+            Some(i) => &mut self.fields[i],
+            None => {
+                self.fields.push(DefinedField {
+                    macro_name: macro_name.to_string(),
+                    position: None,
+                    width: None,
+                    span,
+                });
+                self.fields.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+pub fn registers_from_c_defines(file: &Path) -> Result<Vec<RegisterCluster>> {
+    let header = fs::read_to_string(file)
+        .with_context(|| format!("Error reading file: {}", file.display()))?;
+    parse_c_defines(file, &header).map_err(|e| in_file(e, file))
+}
+
+/// Registers of the header `file` whose text is `header`
+fn parse_c_defines(file: &Path, header: &str) -> Result<Vec<RegisterCluster>> {
+    let tokens = tokenize(header)?;
+    // A diagnostic pointing at `span` of the header
+    let at =
+        |span: Range<usize>, message: String| Diagnostic::new(header, span, message).in_file(file);
+
+    // Macros are prefixed with the peripheral name, GLB_ in glb_reg.h
+    let prefix = file
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(|stem| format!("{}_", stem.trim_end_matches("_reg").to_ascii_uppercase()))
+        .unwrap_or_default();
+    let field_name = |macro_name: &str| {
+        macro_name
+            .strip_prefix(prefix.as_str())
+            .unwrap_or(macro_name)
+            .to_ascii_lowercase()
+    };
+
+    let mut registers = Vec::<DefinedRegister>::new();
+    // The `/* 0x0 : soc_info0 */` comment of the next register
+    let mut register_comment = None;
+
+    for token in tokens {
+        if token.kind == TokenKind::Comment {
+            if let Some(comment) = parse_register_header(token.comment_text()) {
+                register_comment = Some(comment);
+            }
+            continue;
+        }
+        let Some((macro_name, value)) = parse_define(token.text) else {
+            continue;
+        };
+        // The value as a number, or an error pointing at it
+        let number = || -> Result<u32> {
+            define_value(value)
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or_else(|| {
+                    let start =
+                        token.start + (value.as_ptr() as usize - token.text.as_ptr() as usize);
+                    let span = start..start + value.len();
+                    at(span, format!("Can't read the value of {}", macro_name)).into()
+                })
+        };
+
+        if let Some(register) = macro_name.strip_suffix("_OFFSET") {
+            let offset = number()?;
+            let name = match register_comment.take() {
+                Some((comment_offset, name)) => {
+                    if comment_offset != offset {
+                        let message = format!(
+                            "Register '{}' is commented at offset 0x{:x} but defined at 0x{:x}",
+                            name, comment_offset, offset
+                        );
+                        println!("{}", at(token.span(), message).warning().render());
+                    }
+                    name.to_string()
+                }
+                None => field_name(register),
+            };
+            registers.push(DefinedRegister {
+                name,
+                offset,
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let (field, is_position) = match (
+            macro_name.strip_suffix("_POS"),
+            macro_name.strip_suffix("_LEN"),
+        ) {
+            (Some(field), _) => (field, true),
+            (_, Some(field)) => (field, false),
+            _ => continue,
+        };
+        // Position macros before the first register belong to something else
+        let Some(register) = registers.last_mut() else {
+            continue;
+        };
+        let value = number()?;
+        let field = register.field(field, token.span());
+        if is_position {
+            field.position = Some(value);
+        } else {
+            field.width = Some(value);
+        }
+    }
+
+    registers
+        .into_iter()
+        .map(|register| {
+            let mut fields = Vec::<Field>::new();
+            for field in &register.fields {
+                let (Some(position), Some(width)) = (field.position, field.width) else {
+                    let message = format!(
+                        "{} of register '{}' needs both _POS and _LEN, skipping it",
+                        field.macro_name, register.name
+                    );
+                    println!("{}", at(field.span.clone(), message).warning().render());
+                    continue;
+                };
+                let bit_range = BitRange::from_offset_width(position, width);
+                if width == 0 || bit_range.msb() >= DEFAULT_REGISTER_SIZE {
+                    let message = format!(
+                        "Field {} doesn't fit in the {} bits of register '{}'",
+                        field.macro_name, DEFAULT_REGISTER_SIZE, register.name
+                    );
+                    return Err(at(field.span.clone(), message).into());
+                }
+                fields.push(Field::Single(
+                    FieldInfo::builder()
+                        .name(field_name(&field.macro_name))
+                        .bit_range(bit_range)
+                        .build(ValidateLevel::Strict)?,
+                ));
+            }
+
+            Ok(RegisterCluster::Register(Register::Single(
+                RegisterInfo::builder()
+                    .name(register.name)
+                    .address_offset(register.offset)
+                    .fields(Some(fields))
+                    .build(ValidateLevel::Weak)?,
+            )))
+        })
+        .collect()
+}
+
+/// Splits `#define NAME value` into name and value. Function like macros are skipped.
+pub(crate) fn parse_define(directive: &str) -> Option<(&str, &str)> {
+    let rest = directive
+        .strip_prefix('#')?
+        .trim_start()
+        .strip_prefix("define")?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let rest = rest.trim_start();
+    let name_end = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len());
+    if rest[name_end..].starts_with('(') {
+        return None;
+    }
+
+    let value = &rest[name_end..];
+    let value = value
+        .find("/*")
+        .or_else(|| value.find("//"))
+        .map_or(value, |c| &value[..c]);
+
+    Some((&rest[..name_end], value.trim()))
+}
+
+/// Numeric value of a define, `(27U)`, `0x10` or `((uint32_t)0x20000000)`
+pub(crate) fn define_value(value: &str) -> Option<u64> {
+    // Line continuations
+    let value = value.replace(['\\', '\r', '\n'], " ");
+    let mut value = value.trim();
+    while value.starts_with('(') {
+        // Index of the parenthesis closing the first one
+        let mut depth = 0;
+        let close = value.find(|c| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            depth == 0
+        })?;
+        let (inner, rest) = (&value[1..close], value[close + 1..].trim());
+        if rest.is_empty() {
+            value = inner.trim();
+        } else if inner
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ' ')
+        {
+            // A cast like (uint32_t)
+            value = rest;
+        } else {
+            return None;
+        }
+    }
+
+    parse_literal(value, 10).ok().map(|literal| literal.value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLB_REG_H: &str = "#ifndef  __GLB_REG_H__
+#define  __GLB_REG_H__
+
+#include \"bl808.h\"
+
+/* 0x0 : soc_info0 */
+#define GLB_SOC_INFO0_OFFSET                                    (0x0)
+#define GLB_CHIP_RDY                                            GLB_CHIP_RDY
+#define GLB_CHIP_RDY_POS                                        (27U)
+#define GLB_CHIP_RDY_LEN                                        (1U)
+#define GLB_CHIP_RDY_MSK                                        (((1U<<GLB_CHIP_RDY_LEN)-1)<<GLB_CHIP_RDY_POS)
+#define GLB_CHIP_RDY_UMSK                                       (~(((1U<<GLB_CHIP_RDY_LEN)-1)<<GLB_CHIP_RDY_POS))
+#define GLB_ID_POS                                              (28U)
+#define GLB_ID_LEN                                              (4U)
+
+/* 0x50 : sys_cfg0 */
+#define GLB_SYS_CFG0_OFFSET                                     (0x50)
+#define GLB_REG_PLL_EN_POS \\
+    (0U)
+#define GLB_REG_PLL_EN_LEN                                      (1U)
+
+#define GLB_FLAG_OFFSET                                         (0x60)
+#define GLB_FLAG_POS                                            (3U)
+#define GLB_FLAG_LEN                                            (2U)
+
+#endif  /* __GLB_REG_H__ */
+";
+
+    /// Name, offset and the fields' name, offset and width
+    type Summary = (String, u32, Vec<(String, u32, u32)>);
+
+    fn registers(text: &str) -> Result<Vec<Summary>> {
+        Ok(parse_c_defines(Path::new("glb_reg.h"), text)?
+            .iter()
+            .map(|cluster| {
+                let RegisterCluster::Register(register) = cluster else {
+                    panic!("not a register");
+                };
+                let fields = register
+                    .fields()
+                    .map(|f| (f.name.clone(), f.bit_range.offset, f.bit_range.width))
+                    .collect();
+                (register.name.clone(), register.address_offset, fields)
+            })
+            .collect())
+    }
+
+    #[test]
+    fn registers_and_fields() {
+        assert_eq!(
+            registers(GLB_REG_H).unwrap(),
+            [
+                (
+                    "soc_info0".to_string(),
+                    0x0,
+                    vec![("chip_rdy".to_string(), 27, 1), ("id".to_string(), 28, 4)]
+                ),
+                (
+                    "sys_cfg0".to_string(),
+                    0x50,
+                    vec![("reg_pll_en".to_string(), 0, 1)]
+                ),
+                // Named after the macro without a register comment
+                ("flag".to_string(), 0x60, vec![("flag".to_string(), 3, 2)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn fields_need_position_and_width() {
+        let text = "#define GLB_A_OFFSET (0x4)\n#define GLB_B_POS (1U)\n";
+        assert_eq!(
+            registers(text).unwrap(),
+            [("a".to_string(), 0x4, Vec::new())]
+        );
+    }
+
+    #[test]
+    fn fields_outside_the_register() {
+        let text = "#define GLB_A_OFFSET (0x4)\n#define GLB_B_POS (30U)\n#define GLB_B_LEN (4U)\n";
+        let error = registers(text)
+            .unwrap_err()
+            .downcast::<Diagnostic>()
+            .unwrap();
+        // At the first define of the field
+        assert_eq!((error.line, error.column), (2, 1));
+        assert_eq!(
+            error.message,
+            "Field GLB_B doesn't fit in the 32 bits of register 'a'"
+        );
+    }
+
+    #[test]
+    fn unreadable_values() {
+        let text = "#define GLB_A_OFFSET (0x4)\n#define GLB_B_POS (GLB_X + 1)\n";
+        let error = registers(text)
+            .unwrap_err()
+            .downcast::<Diagnostic>()
+            .unwrap();
+        assert_eq!((error.line, error.column, error.length), (2, 19, 11));
+        assert_eq!(error.message, "Can't read the value of GLB_B_POS");
+    }
+
+    #[test]
+    fn defines() {
+        assert_eq!(
+            parse_define("#define GLB_ID_POS (28U) /* id */"),
+            Some(("GLB_ID_POS", "(28U)"))
+        );
+        assert_eq!(parse_define("#  define EMPTY"), Some(("EMPTY", "")));
+        assert_eq!(parse_define("#define BL_RD_WORD(addr) (*(addr))"), None);
+        assert_eq!(parse_define("#include \"bl808.h\""), None);
+        assert_eq!(parse_define("#defineX 1"), None);
+    }
+
+    #[test]
+    fn define_values() {
+        assert_eq!(define_value("(27U)"), Some(27));
+        assert_eq!(define_value("0x10"), Some(0x10));
+        assert_eq!(define_value("((uint32_t)0x20000000)"), Some(0x2000_0000));
+        assert_eq!(define_value("\\\n    (16U)"), Some(16));
+        assert_eq!(define_value("(GLB_BASE + 0x10)"), None);
+        assert_eq!(define_value("(1U<<GLB_CHIP_RDY_LEN)"), None);
+    }
+}
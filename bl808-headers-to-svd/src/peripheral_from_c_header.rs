@@ -1,148 +1,637 @@
 use std::{
     fs::{self},
-    path::Path,
+    ops::Range,
+    path::{Path, PathBuf},
 };
 
 use anyhow::{anyhow, Context, Result};
-use pest::Parser;
-extern crate pest;
-
 use svd_rs::{
-    Access, Field, FieldInfo, PeripheralInfo, Register, RegisterCluster, RegisterInfo,
-    ValidateLevel,
+    BitRange, Cluster, ClusterInfo, DimElement, Field, FieldInfo, Register, RegisterCluster,
+    RegisterInfo, ValidateLevel,
 };
 
-#[derive(Parser)]
-#[grammar = "peripheral_from_c_header.pest"] // relative to src
-struct HeaderRegParser;
-
-pub fn peripheral_from_c_header(
-    file: &Path,
-    base_address: u64,
-    name: String,
-) -> Result<PeripheralInfo> {
-    let new_registers = registers_from_c_header(file)?;
-
-    Ok(PeripheralInfo::builder()
-        .name(name)
-        .registers(Some(new_registers))
-        .base_address(base_address)
-        .build(ValidateLevel::Weak)
-        .unwrap())
-}
+use crate::access::parse_access_mode;
+use crate::c_struct::{Aggregate, AggregateKind, CHeader, CType, Member};
+use crate::diagnostic::{in_file, Diagnostic};
+use crate::literal::parse_literal;
+use crate::register_source::{RegisterBlock, RegisterSource};
+use crate::reset_value::RegisterReset;
+
+/// Register width of the device, registers of other widths get an explicit size
+pub(crate) const DEFAULT_REGISTER_SIZE: u32 = 32;
 
-pub fn append_registers_from_c_header(file: &Path, peripheral: &mut PeripheralInfo) -> Result<()> {
-    let mut new_registers = registers_from_c_header(file)?;
+/// Register struct from an SDK `*_reg.h` header
+#[derive(Debug, Clone)]
+pub struct CHeaderSource {
+    pub path: PathBuf,
+}
 
-    match &mut peripheral.registers {
-        Some(registers) => registers.append(&mut new_registers),
-        None => peripheral.registers = Some(new_registers),
+impl CHeaderSource {
+    pub fn new(path: impl Into<PathBuf>) -> CHeaderSource {
+        CHeaderSource { path: path.into() }
     }
+}
 
-    Ok(())
+impl RegisterSource for CHeaderSource {
+    fn load(&self) -> Result<RegisterBlock> {
+        Ok(RegisterBlock {
+            base_address: None,
+            description: None,
+            registers: registers_from_c_header(&self.path)?,
+            translations: Vec::new(),
+        })
+    }
 }
 
 pub fn registers_from_c_header(file: &Path) -> Result<Vec<RegisterCluster>> {
     let package_string = fs::read_to_string(file)
         .with_context(|| format!("Error reading file: {}", file.display()))?;
+    parse_c_header(file, &package_string)
+}
 
-    let reg_file = HeaderRegParser::parse(Rule::reg_file, &package_string)?
-        .next()
-        .unwrap();
+/// Registers of the header `file` whose text is `package_string`
+fn parse_c_header(file: &Path, package_string: &str) -> Result<Vec<RegisterCluster>> {
+    let header = CHeader::parse(package_string).map_err(|e| in_file(e, file))?;
+    let peripheral = register_struct(&header)
+        .ok_or_else(|| anyhow!("No register struct in {}", file.display()))?;
 
-    if Rule::reg_file != reg_file.as_rule() {
-        return Err(anyhow!("Missing reg_file rule"));
-    }
-    let mut reg_file = reg_file.into_inner();
+    let mut extractor = RegisterExtractor {
+        file,
+        text: package_string,
+        registers: Vec::new(),
+        drift: 0,
+        comment_base: 0,
+    };
+    extractor.add_members(peripheral, 0)?;
+
+    Ok(extractor.registers)
+}
+
+/// The struct describing the peripheral, `struct glb_reg`. Falls back to the first struct
+/// of the header for headers that name it differently.
+fn register_struct<'h, 'a>(header: &'h CHeader<'a>) -> Option<&'h Aggregate<'a>> {
+    let structs = || {
+        header
+            .aggregates
+            .iter()
+            .filter(|a| a.kind == AggregateKind::Struct)
+    };
+    structs()
+        .find(|a| a.tag.is_some_and(|tag| tag.ends_with("_reg")))
+        .or_else(|| structs().next())
+}
 
-    let peripheral = reg_file.next().unwrap();
-    if Rule::peripheral != peripheral.as_rule() {
-        return Err(anyhow!("Missing peripheral."));
+/// Turns the members of a register struct into SVD registers
+struct RegisterExtractor<'f> {
+    file: &'f Path,
+    /// Text of the header, for diagnostics
+    text: &'f str,
+    registers: Vec<RegisterCluster>,
+    /// How far the comment offsets have drifted from the struct layout, so one layout
+    /// mistake is only reported once
+    drift: i64,
+    /// Offset the comments are relative to, the start of the cluster for members of
+    /// cluster arrays
+    comment_base: u32,
+}
+
+impl RegisterExtractor<'_> {
+    /// An error pointing at `span` of the header
+    fn at(&self, span: Range<usize>, message: String) -> Diagnostic {
+        Diagnostic::new(self.text, span, message).in_file(self.file)
     }
-    let mut peripheral = peripheral.into_inner();
 
-    let mut _peripheral_name = peripheral.next().unwrap().as_str();
-    _peripheral_name = &_peripheral_name[..(_peripheral_name.len() - 4)];
+    /// Adds the registers among the members of `aggregate`, which starts at `base`
+    fn add_members(&mut self, aggregate: &Aggregate, base: u64) -> Result<()> {
+        let layout = aggregate.layout()?;
+
+        for (member, member_offset) in aggregate.members.iter().zip(layout.offsets) {
+            let header = member
+                .leading_comments
+                .iter()
+                .rev()
+                .find_map(|comment| parse_register_header(comment.comment_text()));
+            let name = header
+                .map(|(_, name)| name)
+                .or(member.name)
+                .unwrap_or_default();
+            let struct_offset = (base + member_offset) as i64 + self.drift;
+            let offset = match header {
+                Some((offset, _)) => {
+                    let offset = offset.checked_sub(self.comment_base).unwrap_or(offset);
+                    self.check_offset(name, offset, struct_offset);
+                    offset
+                }
+                None => struct_offset as u32,
+            };
+
+            // Arrays of registers or of register groups, multi dimensional ones flattened
+            let dim = if member.dimensions.is_empty() {
+                None
+            } else {
+                Some(
+                    DimElement::builder()
+                        .dim(member.element_count() as u32)
+                        .dim_increment(member.ty.size()?.0 as u32)
+                        .build(ValidateLevel::Weak)?,
+                )
+            };
+            let dim_name = if dim.is_some() && !name.contains("%s") {
+                format!("{}[%s]", name)
+            } else {
+                name.to_string()
+            };
 
-    let registers = peripheral.next().unwrap();
-    if Rule::registers != registers.as_rule() {
-        return Err(anyhow!("Expected register listing"));
+            match &member.ty {
+                _ if is_reserved(member, name, header.is_some()) => {}
+                CType::Scalar(_) if member.bit_width.is_some() => println!(
+                    "Warning: {}:{}: ignoring bitfield '{}' outside of a register",
+                    self.file.display(),
+                    member.line,
+                    name
+                ),
+                CType::Aggregate(inner) if inner.kind == AggregateKind::Union => {
+                    // The bitfield struct and the word of the register
+                    let bitfields = inner.members.iter().find_map(|m| match &m.ty {
+                        CType::Aggregate(a) if a.kind == AggregateKind::Struct => Some(a),
+                        _ => None,
+                    });
+                    let size = inner.layout()?.size as u32 * 8;
+                    let fields = bitfields.map_or(&[][..], |a| &a.members[..]);
+                    self.add_register(&dim_name, member.span.clone(), offset, size, fields, dim)?;
+                }
+                CType::Aggregate(inner) if inner.has_bitfields() => {
+                    let size = inner.layout()?.size as u32 * 8;
+                    self.add_register(
+                        &dim_name,
+                        member.span.clone(),
+                        offset,
+                        size,
+                        &inner.members,
+                        dim,
+                    )?;
+                }
+                // A group of registers, repeated groups become a cluster array
+                CType::Aggregate(inner) => match dim {
+                    None => self.add_members(inner, base + member_offset)?,
+                    Some(dim) => {
+                        let mut cluster = RegisterExtractor {
+                            file: self.file,
+                            text: self.text,
+                            registers: Vec::new(),
+                            drift: 0,
+                            comment_base: offset,
+                        };
+                        cluster.add_members(inner, 0)?;
+                        let cluster = ClusterInfo::builder()
+                            .name(dim_name)
+                            .address_offset(offset)
+                            .children(cluster.registers)
+                            .build(ValidateLevel::Weak)?;
+                        self.registers
+                            .push(RegisterCluster::Cluster(Cluster::Array(cluster, dim)));
+                    }
+                },
+                CType::Scalar(_) => {
+                    let size = member.ty.size()?.0 as u32 * 8;
+                    self.add_register(&dim_name, member.span.clone(), offset, size, &[], dim)?;
+                }
+            }
+        }
+
+        Ok(())
     }
 
-    let mut new_registers = Vec::<RegisterCluster>::new();
+    /// Adds a register `size` bits wide with the bitfields `fields`, or an array of them
+    /// if there's a `dim`. `span` is the register's member, warnings about the whole
+    /// register point there.
+    fn add_register(
+        &mut self,
+        name: &str,
+        span: Range<usize>,
+        offset: u32,
+        size: u32,
+        fields: &[Member],
+        dim: Option<DimElement>,
+    ) -> Result<()> {
+        let warn = |diagnostic: Diagnostic| println!("{}", diagnostic.warning().render());
+        let mut svd_fields = Vec::<Field>::new();
+        let mut reset = RegisterReset::default();
+        let mut layout = FieldLayout::default();
 
-    for register in registers.into_inner() {
-        match register.as_rule() {
-            Rule::reserved_register => {
-                // TODO
+        for field in fields {
+            let field_type_bits = field.ty.size()?.0 as u32 * 8;
+            let bit_width = field.bit_width.unwrap_or(field_type_bits);
+            let Some(field_name) = field.name else {
+                // Unnamed bitfields only pad
+                layout.skip(bit_width);
+                continue;
+            };
+            if bit_width > field_type_bits {
+                let message = format!(
+                    "Field '{}' on register '{}' is {} bits wide but its type has {} bits",
+                    field_name, name, bit_width, field_type_bits
+                );
+                return Err(self.at(field.span.clone(), message).into());
             }
-            Rule::register => {
-                let mut register_inner = register.into_inner();
-                let mut register_header = register_inner.next().unwrap().into_inner();
-                let offset: u32 =
-                    u32::from_str_radix(register_header.next().unwrap().as_str(), 16)?;
-                let register_name = register_header.next().unwrap().as_str();
-
-                let mut fields = Vec::<Field>::new();
-
-                for field in register_inner {
-                    let mut field_data = field.into_inner();
-                    let field_name = field_data.next().unwrap().as_str();
-                    let _size: u8 = field_data.next().unwrap().as_str().parse()?;
-                    let first_position = field_data.next().unwrap();
-                    let start_pos: u32 = first_position.as_str().parse()?;
-                    let end_pos: u32 = match first_position.as_rule() {
-                        Rule::field_pos_start => field_data.next().unwrap().as_str().parse()?,
-                        _ => start_pos,
-                    };
-
-                    let access: Option<Access> = match field_data.next().unwrap().as_str() {
-                        "RW" => Some(svd_rs::Access::ReadWrite),
-                        "rw" => Some(svd_rs::Access::ReadWrite),
-                        "RWAC" => Some(svd_rs::Access::ReadWrite),
-                        "RW1C" => Some(svd_rs::Access::ReadWrite),
-                        "ROC" => Some(svd_rs::Access::ReadOnly),
-                        "r/w" => Some(svd_rs::Access::ReadWrite),
-                        "w" => Some(svd_rs::Access::WriteOnly),
-                        "rsvd" => None,
-                        "RSVD" => None,
-                        "None" => None,
-                        "w1c" => Some(svd_rs::Access::WriteOnce),
-                        "RO" => Some(svd_rs::Access::ReadOnly),
-                        "r" => Some(svd_rs::Access::ReadOnly),
-                        "R" => Some(svd_rs::Access::ReadOnly),
-                        "WO" => Some(svd_rs::Access::WriteOnly),
-                        "w1p" => Some(svd_rs::Access::WriteOnce), // TODO: Think this is the right mapping
-                        access_mode => {
-                            println!("Unknown access mode: {}", access_mode);
-                            return Err(anyhow!(format!("Unknown access mode: {}", access_mode)));
-                        }
-                    };
-
-                    let _mask: u32 = u32::from_str_radix(field_data.next().unwrap().as_str(), 16)
-                        .unwrap_or_default();
-
-                    let field = FieldInfo::builder()
+
+            let Some(comment) = field.trailing_comment else {
+                let message = format!(
+                    "Field '{}' on register '{}' has no position comment, placing it at bit {}",
+                    field_name, name, layout.next_bit
+                );
+                warn(self.at(field.span.clone(), message));
+                let bit_range = BitRange::from_offset_width(layout.next_bit, bit_width);
+                self.check_fits(field, field_name, bit_range, name, size)?;
+                layout.add(field_name, bit_width, bit_range);
+                svd_fields.push(Field::Single(
+                    FieldInfo::builder()
                         .name(field_name.to_string())
-                        .bit_range(svd_rs::BitRange::from_msb_lsb(start_pos, end_pos))
-                        .access(access)
-                        .build(svd_rs::ValidateLevel::Strict)?;
-                    fields.push(Field::Single(field));
-                }
+                        .bit_range(bit_range)
+                        .build(ValidateLevel::Strict)?,
+                ));
+                continue;
+            };
+            // Bytes of a part of the comment
+            let span_of = |part: &str| {
+                let start =
+                    comment.start + (part.as_ptr() as usize - comment.text.as_ptr() as usize);
+                start..start + part.len()
+            };
+            let (bit_range, access, reset_text) = parse_field_comment(comment.comment_text())
+                .ok_or_else(|| {
+                    let message = format!(
+                        "Can't read the position of field '{}' on register '{}'",
+                        field_name, name
+                    );
+                    self.at(comment.span(), message)
+                })?;
+            self.check_fits(field, field_name, bit_range, name, size)?;
+            let access = parse_access_mode(access)
+                .map_err(|e| self.at(span_of(access), e.to_string()))
+                .with_context(|| format!("Field '{}' on register '{}'", field_name, name))?;
 
-                new_registers.push(RegisterCluster::Register(Register::Single(
-                    RegisterInfo::builder()
-                        .name(register_name.to_string())
-                        .address_offset(offset)
-                        .fields(Some(fields))
-                        .build(ValidateLevel::Weak)?,
-                )));
+            for warning in layout.add(field_name, bit_width, bit_range) {
+                let message = format!("Register '{}': {}", name, warning);
+                warn(self.at(field.span.clone(), message));
             }
-            _ => {
-                return Err(anyhow!("Unmatched register rule"));
+            // The last value of the field comment is the reset value of the field
+            match parse_literal(reset_text, 16) {
+                Ok(value) => {
+                    if !reset.add_field(bit_range, &value) {
+                        let message = format!(
+                            "Reset value {} doesn't fit field '{}' on register '{}'",
+                            reset_text, field_name, name
+                        );
+                        warn(self.at(span_of(reset_text), message));
+                    }
+                }
+                Err(e) => {
+                    let e = e.offset(span_of(reset_text).start);
+                    let message = format!(
+                        "Ignoring reset value of field '{}' on register '{}': {}",
+                        field_name, name, e
+                    );
+                    warn(self.at(e.span(), message));
+                }
+            }
+
+            let field = FieldInfo::builder()
+                .name(field_name.to_string())
+                .bit_range(bit_range)
+                .access(access.access)
+                .modified_write_values(access.modified_write_values)
+                .read_action(access.read_action)
+                .build(ValidateLevel::Strict)?;
+            svd_fields.push(Field::Single(field));
+        }
+
+        if !fields.is_empty() && layout.declared_bits != size {
+            let message = format!(
+                "Bitfields of register '{}' add up to {} bits but the register has {}",
+                name, layout.declared_bits, size
+            );
+            warn(self.at(span, message));
+        }
+
+        let register = RegisterInfo::builder()
+            .name(name.to_string())
+            .address_offset(offset)
+            .size((size != DEFAULT_REGISTER_SIZE).then_some(size))
+            .reset_value(reset.value())
+            .reset_mask(reset.mask())
+            .fields(Some(svd_fields))
+            .build(ValidateLevel::Weak)?;
+        self.registers.push(RegisterCluster::Register(match dim {
+            Some(dim) => Register::Array(register, dim),
+            None => Register::Single(register),
+        }));
+
+        Ok(())
+    }
+
+    /// Fails if `bit_range` of `field` reaches past the `size` bits of its register
+    fn check_fits(
+        &self,
+        field: &Member,
+        field_name: &str,
+        bit_range: BitRange,
+        name: &str,
+        size: u32,
+    ) -> Result<()> {
+        if bit_range.msb() >= size {
+            let message = format!(
+                "Field '{}' doesn't fit in the {} bits of register '{}'",
+                field_name, size, name
+            );
+            return Err(self.at(field.span.clone(), message).into());
+        }
+        Ok(())
+    }
+
+    /// Reports a register whose comment offset isn't where the struct layout puts it
+    fn check_offset(&mut self, name: &str, comment_offset: u32, struct_offset: i64) {
+        if comment_offset as i64 != struct_offset {
+            println!(
+                "Warning: {}: register '{}' is commented at offset 0x{:x} but the struct places it at 0x{:x}",
+                self.file.display(),
+                name,
+                comment_offset,
+                struct_offset
+            );
+            self.drift += comment_offset as i64 - struct_offset;
+        }
+    }
+}
+
+/// Padding between registers, `uint8_t RESERVED_0x4[4]`. Arrays without a register
+/// comment are padding too.
+fn is_reserved(member: &Member, name: &str, has_header: bool) -> bool {
+    let reserved = |name: &str| {
+        let name = name.to_ascii_lowercase();
+        name.starts_with("rsvd") || name.starts_with("reserved")
+    };
+    matches!(member.ty, CType::Scalar(_))
+        && member.bit_width.is_none()
+        && ((!member.dimensions.is_empty() && !has_header)
+            || reserved(name)
+            || member.name.is_some_and(reserved))
+}
+
+/// Splits a `0x10 : name` register comment into offset and name
+pub(crate) fn parse_register_header(comment: &str) -> Option<(u32, &str)> {
+    if !comment.starts_with("0x") && !comment.starts_with("0X") {
+        return None;
+    }
+    let end = comment
+        .find(|c: char| c.is_whitespace() || c == ':')
+        .unwrap_or(comment.len());
+    let offset = parse_literal(&comment[..end], 16).ok()?.value as u32;
+    let name = comment[end..].trim_start_matches(|c: char| c.is_whitespace() || c == ':');
+
+    Some((offset, name.trim()))
+}
+
+/// Splits a `[15:8], r/w, 0x0` field comment into bit range, access code and reset value
+fn parse_field_comment(comment: &str) -> Option<(BitRange, &str, &str)> {
+    let (bits, rest) = comment.strip_prefix('[')?.split_once(']')?;
+    let (msb, lsb) = match bits.split_once(':') {
+        Some((msb, lsb)) => (msb.trim().parse().ok()?, lsb.trim().parse().ok()?),
+        None => {
+            let bit = bits.trim().parse().ok()?;
+            (bit, bit)
+        }
+    };
+    if lsb > msb {
+        return None;
+    }
+
+    let mut values = rest.trim_start_matches([',', ' ', '\t']).split(',');
+    let access = values.next()?.trim();
+    let reset = values.next()?.trim();
+
+    Some((BitRange::from_msb_lsb(msb, lsb), access, reset))
+}
+
+/// Follows the bitfields of a register through the struct, to check them against the
+/// positions the header comments give
+#[derive(Default)]
+struct FieldLayout<'a> {
+    /// Where the compiler puts the next bitfield
+    next_bit: u32,
+    /// Sum of the declared bitfield widths
+    declared_bits: u32,
+    fields: Vec<(&'a str, BitRange)>,
+}
+
+impl<'a> FieldLayout<'a> {
+    /// Adds a field declared `bit_width` bits wide at the documented `bit_range`, returns
+    /// the inconsistencies found
+    fn add(&mut self, name: &'a str, bit_width: u32, bit_range: BitRange) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        if bit_width != bit_range.width {
+            warnings.push(format!(
+                "field '{}' is declared {} bits wide but documented at {}",
+                name,
+                bit_width,
+                bit_range.bit_range()
+            ));
+        }
+
+        // Bitfields are packed from the least significant bit up, in declaration order
+        if bit_range.lsb() != self.next_bit {
+            warnings.push(format!(
+                "field '{}' is documented at {} but the struct places it at bit {}",
+                name,
+                bit_range.bit_range(),
+                self.next_bit
+            ));
+        }
+
+        for (other_name, other_range) in &self.fields {
+            if bit_range.lsb() <= other_range.msb() && other_range.lsb() <= bit_range.msb() {
+                warnings.push(format!(
+                    "field '{}' at {} overlaps field '{}' at {}",
+                    name,
+                    bit_range.bit_range(),
+                    other_name,
+                    other_range.bit_range()
+                ));
             }
         }
+
+        // Carry on from the documented position so one mistake is only reported once
+        self.next_bit = bit_range.lsb() + bit_width;
+        self.declared_bits += bit_width;
+        self.fields.push((name, bit_range));
+        warnings
     }
 
-    Ok(new_registers)
+    /// Adds an unnamed padding bitfield
+    fn skip(&mut self, bit_width: u32) {
+        self.next_bit += bit_width;
+        self.declared_bits += bit_width;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLB_REG_H: &str = "struct glb_reg {
+    /* 0x0 : soc_info0 */
+    union {
+        struct {
+            uint32_t reserved_0_26 : 27; /* [26: 0],       rsvd,        0x0 */
+            uint32_t chip_rdy      :  1; /* [   27],          r,        0x0 */
+            uint32_t glb_id        :  4; /* [31:28],          r,        0x6 */
+        } BF;
+        uint32_t WORD;
+    } soc_info0;
+
+    /* 0x4 : sys_cfg0 */
+    union {
+        struct {
+            uint32_t reg_pll_en : 1; /* [    0],        r/w,        0x1 */
+            uint32_t reserved_1_31 : 31; /* [31: 1],       rsvd,        0x0 */
+        } BF;
+        uint32_t WORD;
+    } sys_cfg0;
+};
+";
+
+    fn registers(text: &str) -> Result<Vec<RegisterInfo>> {
+        Ok(parse_c_header(Path::new("glb_reg.h"), text)?
+            .into_iter()
+            .map(|cluster| match cluster {
+                RegisterCluster::Register(register) => (*register).clone(),
+                RegisterCluster::Cluster(_) => panic!("not a register"),
+            })
+            .collect())
+    }
+
+    /// The diagnostic `text` fails with, as line, column and message
+    fn error(text: &str) -> (usize, usize, String) {
+        let error = parse_c_header(Path::new("glb_reg.h"), text)
+            .unwrap_err()
+            .downcast::<Diagnostic>()
+            .unwrap();
+        assert_eq!(error.file.as_deref(), Some(Path::new("glb_reg.h")));
+        (error.line, error.column, error.message)
+    }
+
+    #[test]
+    fn registers_and_fields() {
+        let registers = registers(GLB_REG_H).unwrap();
+        let names: Vec<_> = registers
+            .iter()
+            .map(|r| (r.name.as_str(), r.address_offset))
+            .collect();
+        assert_eq!(names, [("soc_info0", 0), ("sys_cfg0", 4)]);
+
+        let fields: Vec<_> = registers[0]
+            .fields()
+            .map(|f| {
+                (
+                    f.name.as_str(),
+                    f.bit_range.offset,
+                    f.bit_range.width,
+                    f.access,
+                )
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("reserved_0_26", 0, 27, None),
+                ("chip_rdy", 27, 1, Some(svd_rs::Access::ReadOnly)),
+                ("glb_id", 28, 4, Some(svd_rs::Access::ReadOnly)),
+            ]
+        );
+        assert_eq!(registers[0].properties.reset_value, Some(0x6000_0000));
+        assert_eq!(registers[1].properties.reset_value, Some(1));
+    }
+
+    #[test]
+    fn field_comments() {
+        assert_eq!(
+            parse_field_comment("[31:28],          r,        0x6"),
+            Some((BitRange::from_msb_lsb(31, 28), "r", "0x6"))
+        );
+        assert_eq!(
+            parse_field_comment("[    0],        r/w,        0x1"),
+            Some((BitRange::from_msb_lsb(0, 0), "r/w", "0x1"))
+        );
+        assert_eq!(parse_field_comment("[0:31], r, 0x0"), None);
+        assert_eq!(parse_field_comment("[31:28], r"), None);
+        assert_eq!(parse_field_comment("reserved"), None);
+        assert_eq!(
+            parse_register_header("0x10 : sys_cfg0"),
+            Some((0x10, "sys_cfg0"))
+        );
+        assert_eq!(parse_register_header("sys_cfg0"), None);
+    }
+
+    #[test]
+    fn field_layout() {
+        let mut layout = FieldLayout::default();
+        assert!(layout
+            .add("a", 4, BitRange::from_offset_width(0, 4))
+            .is_empty());
+        assert_eq!(
+            layout.add("b", 2, BitRange::from_offset_width(3, 4)),
+            [
+                "field 'b' is declared 2 bits wide but documented at [6:3]",
+                "field 'b' is documented at [6:3] but the struct places it at bit 4",
+                "field 'b' at [6:3] overlaps field 'a' at [3:0]",
+            ]
+        );
+        layout.skip(3);
+        assert_eq!(layout.next_bit, 8);
+        assert_eq!(layout.declared_bits, 9);
+    }
+
+    #[test]
+    fn unreadable_position_comment() {
+        let text = GLB_REG_H.replace("[   27]", "[  27?]");
+        let (line, column, message) = error(&text);
+        assert_eq!((line, column), (6, 42));
+        assert_eq!(
+            message,
+            "Can't read the position of field 'chip_rdy' on register 'soc_info0'"
+        );
+    }
+
+    #[test]
+    fn unknown_access_code() {
+        let text = GLB_REG_H.replace("r/w,", "rw2,");
+        let (line, column, message) = error(&text);
+        // At the access code in the comment
+        assert_eq!((line, column), (15, 57));
+        assert_eq!(message, "Unknown access mode: rw2");
+    }
+
+    #[test]
+    fn fields_outside_the_register() {
+        let text = GLB_REG_H.replace("[31:28]", "[35:32]");
+        let (line, column, message) = error(&text);
+        // At the field name
+        assert_eq!((line, column), (7, 22));
+        assert_eq!(
+            message,
+            "Field 'glb_id' doesn't fit in the 32 bits of register 'soc_info0'"
+        );
+    }
+
+    #[test]
+    fn fields_wider_than_their_type() {
+        let text = GLB_REG_H.replace("uint32_t reg_pll_en : 1;", "uint8_t reg_pll_en : 9; ");
+        let (line, column, message) = error(&text);
+        assert_eq!((line, column), (15, 21));
+        assert_eq!(
+            message,
+            "Field 'reg_pll_en' on register 'sys_cfg0' is 9 bits wide but its type has 8 bits"
+        );
+    }
 }
@@ -1,36 +1,162 @@
-use std::{fs, path::Path};
+use std::{
+    collections::HashMap,
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{anyhow, Context, Result};
-use pest::Parser;
+use pest::{iterators::Pair, Parser};
 extern crate pest;
 
-use svd_rs::{
-    Access, Field, FieldInfo, PeripheralInfo, Register, RegisterCluster, RegisterInfo,
-    ValidateLevel,
-};
+use svd_rs::{BitRange, Field, FieldInfo, Register, RegisterCluster, RegisterInfo, ValidateLevel};
+
+use crate::access::parse_access_mode;
+use crate::diagnostic::{self, Diagnostic};
+use crate::enumerated_values::enumerated_values_from_description;
+use crate::literal::{parse_literal, Literal, LiteralError};
+use crate::manifest::Language;
+use crate::register_source::{RegisterBlock, RegisterSource, Translation};
+use crate::reset_value::RegisterReset;
+use crate::rst_markup::{normalize, DescriptionFormat};
+use crate::rst_table::GridTable;
 
 #[derive(Parser)]
 #[grammar = "peripheral_from_docs.pest"] // relative to src
 struct DocsRegParser;
 
-pub fn peripheral_from_doc_rst(
-    file: &Path,
-    peripheral_name: String,
-    alt_base: Option<u64>,
-) -> Result<PeripheralInfo> {
+/// Register chapter of the reference manual in bl_docs
+#[derive(Debug, Clone)]
+pub struct DocRstSource {
+    pub path: PathBuf,
+    pub language: Language,
+    /// The same chapter in the other language, for its descriptions
+    pub translation: Option<PathBuf>,
+    pub format: DescriptionFormat,
+}
+
+impl DocRstSource {
+    pub fn new(path: impl Into<PathBuf>, language: Language) -> DocRstSource {
+        DocRstSource {
+            path: path.into(),
+            language,
+            translation: None,
+            format: DescriptionFormat::default(),
+        }
+    }
+
+    pub fn with_translation(self, path: impl Into<PathBuf>) -> DocRstSource {
+        DocRstSource {
+            translation: Some(path.into()),
+            ..self
+        }
+    }
+
+    pub fn with_format(self, format: DescriptionFormat) -> DocRstSource {
+        DocRstSource { format, ..self }
+    }
+}
+
+impl RegisterSource for DocRstSource {
+    /// Registers come from `path`. Descriptions are English where either chapter has one,
+    /// the Chinese ones are kept as translations.
+    fn load(&self) -> Result<RegisterBlock> {
+        let mut block = registers_from_doc_rst(&self.path, self.format)?;
+
+        let translation = match &self.translation {
+            Some(path) if path.exists() => match translation_from_doc_rst(path, self.format) {
+                Ok(translation) => translation,
+                Err(e) => {
+                    let e = e.context(format!("Ignoring translation {}", path.display()));
+                    println!("{}\n", diagnostic::report(&e));
+                    RegisterBlock::default()
+                }
+            },
+            _ => RegisterBlock::default(),
+        };
+        merge_translation(&mut block, translation, self.language);
+
+        Ok(block)
+    }
+
+    /// The reference manual is the one source whose peripherals are validated strictly
+    fn validate_level(&self) -> ValidateLevel {
+        ValidateLevel::Strict
+    }
+}
+
+/// Reads the registers of an RST register chapter. The base address is the address of the
+/// first register in the file, descriptions are normalized to `format`.
+pub fn registers_from_doc_rst(file: &Path, format: DescriptionFormat) -> Result<RegisterBlock> {
     let file_string = fs::read_to_string(file)
-        .with_context(|| format!("Error reading file: {}", file.display()))?
-        .replace("Vsync|Hsync", "Vsync or Hsync");
+        .with_context(|| format!("Error reading file: {}", file.display()))?;
+    parse_doc_rst(file, &file_string, format, true)
+}
+
+/// [`registers_from_doc_rst`] without warnings, for the translation of a chapter whose
+/// warnings were shown already
+fn translation_from_doc_rst(file: &Path, format: DescriptionFormat) -> Result<RegisterBlock> {
+    let file_string = fs::read_to_string(file)
+        .with_context(|| format!("Error reading file: {}", file.display()))?;
+    parse_doc_rst(file, &file_string, format, false)
+}
 
-    let peripheral_file = DocsRegParser::parse(Rule::PERIPHERAL_FILE, &file_string)
-        .with_context(|| format!("Error parsing {}", file.display()))? // unwrap the parse result
+/// [`registers_from_doc_rst`] for the text of `file`, printing warnings if `warnings`
+fn parse_doc_rst(
+    file: &Path,
+    file_string: &str,
+    format: DescriptionFormat,
+    warnings: bool,
+) -> Result<RegisterBlock> {
+    // An error pointing at `span` of the file
+    let at = |span: Range<usize>, message: String| {
+        Diagnostic::new(file_string, span, message).in_file(file)
+    };
+    let warn = |diagnostic: Diagnostic| {
+        if warnings {
+            println!("{}", diagnostic.warning().render());
+        }
+    };
+
+    let peripheral_file = DocsRegParser::parse(Rule::PERIPHERAL_FILE, file_string)
+        .map_err(|e| Diagnostic::from_pest(file_string, e).in_file(file))? // unwrap the parse result
         .next()
         .with_context(|| format!("Error parsing {}", file.display()))?; // get and unwrap the `peripheral_file` rule; never fails
 
     if Rule::PERIPHERAL_FILE != peripheral_file.as_rule() {
         return Err(anyhow!("Missing PERIPHERAL_FILE rule"));
     }
-    let registers = peripheral_file.into_inner();
+    let mut registers = peripheral_file.into_inner();
+    let title = registers
+        .next()
+        .with_context(|| "Failed unwrapping document title")?
+        .as_str()
+        .trim();
+    let intro = registers
+        .next()
+        .with_context(|| "Failed unwrapping document introduction")?
+        .as_str();
+    let description = match prose(intro) {
+        Some(intro) if title.is_empty() => Some(intro),
+        Some(intro) => Some(format!("{}\n\n{}", title, intro)),
+        None => (!title.is_empty()).then(|| title.to_string()),
+    }
+    .map(|description| normalize(&description, format));
+    let summary_pair = registers
+        .next()
+        .with_context(|| "Failed unwrapping register summary table")?;
+    let summary_span = first_line(&summary_pair);
+    let summary = summary_table(summary_pair, |e| {
+        warn(at(
+            e.span(),
+            format!("Ignoring address in the summary table: {}", e),
+        ))
+    })
+    .map_err(|e| at(summary_span, e.to_string()).rule("GRID_TABLE"))?;
+    let mut summary_descriptions = HashMap::new();
+    for entry in &summary {
+        summary_descriptions.insert(entry.name.to_ascii_lowercase(), entry.description.clone());
+    }
     let mut new_registers = Vec::<RegisterCluster>::new();
 
     let mut base_address: u32 = 0;
@@ -42,90 +168,122 @@ pub fn peripheral_from_doc_rst(
         let mut register = register.into_inner();
         let register_title = register
             .next()
-            .with_context(|| "Failed unwrapping register title")?
-            .as_str();
-        let address: u32 = parse_hex_or_zero(
-            register
-                .next()
-                .with_context(|| "Failed unwrapping address")?
-                .as_str(),
-        );
+            .with_context(|| "Failed unwrapping register title")?;
+        let title_span = register_title.as_span().start()..register_title.as_span().end();
+        let register_title = register_title.as_str();
+        let address = register
+            .next()
+            .with_context(|| "Failed unwrapping address")?;
+        let address = parse_literal(address.as_str(), 16)
+            .map_err(|e| {
+                let e = e.offset(address.as_span().start());
+                at(e.span(), e.to_string()).rule("ADDRESS_VALUE")
+            })
+            .with_context(|| format!("Address of register '{}'", register_title))?
+            .value as u32;
         if base_address == 0 {
             base_address = address;
         }
-        let offset: u32 = address - base_address;
+        let offset = address.checked_sub(base_address).ok_or_else(|| {
+            let message = format!(
+                "Register '{}' at 0x{:x} comes before the first register of the chapter at 0x{:x}",
+                register_title, address, base_address
+            );
+            at(title_span, message)
+        })?;
+        let description = prose(
+            register
+                .next()
+                .with_context(|| "Failed unwrapping register description")?
+                .as_str(),
+        )
+        .or_else(|| {
+            summary_descriptions
+                .get(&register_title.to_ascii_lowercase())
+                .cloned()
+                .flatten()
+        })
+        .map(|description| normalize(&description, format));
 
-        let mut table = register
-            .next()
-            .with_context(|| "Getting table")?
-            .into_inner();
-        table.next(); // Skip table header
+        let table = register.next().with_context(|| "Getting table")?;
+        let table_span = first_line(&table);
+        let (table, columns) = GridTable::parse(table.as_str(), table.as_span().start())
+            .and_then(|table| FieldColumns::find(&table).map(|columns| (table, columns)))
+            .map_err(|e| at(table_span.clone(), e.to_string()).rule("GRID_TABLE"))
+            .with_context(|| format!("Field table of register '{}'", register_title))?;
+        for (column, what) in [(columns.access, "access"), (columns.reset, "reset value")] {
+            if column.is_none() {
+                let message = format!(
+                    "No {} column in the field table of register '{}', its fields have none",
+                    what, register_title
+                );
+                warn(at(table_span.clone(), message));
+            }
+        }
 
         let mut fields = Vec::<Field>::new();
+        let mut reset = RegisterReset::default();
 
-        for field in table {
-            if field.as_rule() == Rule::ROW_FIVE_CELL {
-                let mut field_inner = field.into_inner();
-                // TODO: Parse descriptions that can be enumerated values
-                field_inner.next();
-                field_inner.next();
-                field_inner.next();
-                field_inner.next();
-                let fields_pos = fields.len() - 1;
-                let current_description = match fields[fields_pos].description.as_ref() {
-                    Some(description) => description,
-                    None => "",
-                };
-                fields[fields_pos].description = Some(format!(
-                    "{}\n{}",
-                    current_description,
-                    field_inner
-                        .next()
-                        .with_context(|| "Error Processing additional description text 2")?
-                        .as_str()
-                ));
+        for row in &table.rows {
+            let bits = row.text(columns.bits);
+            let description = row.text(columns.description);
+            // Rows without bits continue the description of the field above
+            if bits.is_empty() {
+                if let Some(field) = fields.last_mut().filter(|_| !description.is_empty()) {
+                    let current_description = field.description.as_deref().unwrap_or_default();
+                    field.description = Some(format!("{}\n{}", current_description, description));
+                }
                 continue;
             }
-            let mut field_inner = field.into_inner();
-            let first_bit_rule = field_inner.next().unwrap();
-            let start_bit: u32;
-            let end_bit: u32;
-            if Rule::BIT_START == first_bit_rule.as_rule() {
-                end_bit = parse_u32_or_zero(first_bit_rule.as_str());
-                start_bit = parse_u32_or_zero(field_inner.next().unwrap().as_str());
-            } else {
-                start_bit = parse_u32_or_zero(first_bit_rule.as_str());
-                end_bit = start_bit;
-            }
-            let name = field_inner.next().unwrap().as_str();
-            let access: Option<Access> = match field_inner.next().unwrap().as_str() {
-                "r/w" => Some(svd_rs::Access::ReadWrite),
-                "w" => Some(svd_rs::Access::WriteOnly),
-                "rsvd" => None,
-                "" => None,
-                "HwInit" => Some(svd_rs::Access::ReadOnly),
-                "roc" => Some(svd_rs::Access::ReadOnly),
-                "roc/rw" => Some(svd_rs::Access::ReadWrite),
-                "rw" => Some(svd_rs::Access::ReadWrite),
-                "rwac" => Some(svd_rs::Access::ReadWrite),
-                "rw1c" => Some(svd_rs::Access::ReadWrite),
-                "w1c" => Some(svd_rs::Access::WriteOnce),
-                "w1p" => Some(svd_rs::Access::WriteOnce), // Not 100% sure this is right
-                "r" => Some(svd_rs::Access::ReadOnly),
-                access_mode => {
-                    return Err(anyhow!(format!("Unknown access mode: {}", access_mode)));
+            let bit_range = parse_bits(bits)
+                .map_err(|e| at(row.span(columns.bits), e.to_string()))
+                .with_context(|| format!("Bits of register '{}'", register_title))?;
+            let name = row.text(columns.name);
+            let access = parse_access_mode(row.text(columns.access))
+                .map_err(|e| at(row.span(columns.access), e.to_string()))
+                .with_context(|| format!("Field '{}' of register '{}'", name, register_title))?;
+            let reset_text = row.text(columns.reset);
+            let reset_span = row.span(columns.reset);
+            match parse_reset_value(reset_text).map_err(|e| e.offset(reset_span.start)) {
+                Ok(Some(value)) => {
+                    if value.width.is_some_and(|width| width != bit_range.width) {
+                        let message = format!(
+                            "Reset value {} of field '{}' on register '{}' is declared {} bits wide, the field is {}",
+                            reset_text,
+                            name,
+                            register_title,
+                            value.width.unwrap(),
+                            bit_range.width
+                        );
+                        warn(at(reset_span.clone(), message));
+                    }
+                    if !reset.add_field(bit_range, &value) {
+                        let message = format!(
+                            "Reset value {} doesn't fit field '{}' on register '{}'",
+                            reset_text, name, register_title
+                        );
+                        warn(at(reset_span, message));
+                    }
                 }
-            };
-            field_inner.next(); // skip reset
-                                //let reset: u32 = parse_hex_or_zero(field_inner.next().expect("mask").as_str());
-            let description = field_inner.next().expect("description").as_str().to_owned();
+                Ok(None) => {}
+                Err(e) => {
+                    let message = format!(
+                        "Ignoring reset value of field '{}' on register '{}': {}",
+                        name, register_title, e
+                    );
+                    warn(at(e.span(), message));
+                }
+            }
             //println!("Adding field: {}", name.to_string());
             let field = FieldInfo::builder()
                 .name(name.to_string())
-                .bit_range(svd_rs::BitRange::from_msb_lsb(end_bit, start_bit))
-                .access(access)
-                .description(Some(description))
+                .bit_range(bit_range)
+                .access(access.access)
+                .modified_write_values(access.modified_write_values)
+                .read_action(access.read_action)
+                .description(Some(description.to_string()))
                 .build(svd_rs::ValidateLevel::Weak)
+                .map_err(|e| at(row.span(columns.name), e.to_string()))
                 .with_context(|| {
                     format!(
                         "Building field: '{}' on register '{}'",
@@ -135,46 +293,488 @@ pub fn peripheral_from_doc_rst(
             fields.push(Field::Single(field));
         }
 
+        for field in fields.iter_mut() {
+            let Some(description) = field.description.take() else {
+                continue;
+            };
+            // Values are read from the plain text, without markup in their names
+            if let Some(values) = enumerated_values_from_description(
+                &normalize(&description, DescriptionFormat::Plain),
+                field.bit_range.width,
+            ) {
+                field.enumerated_values = vec![values];
+            }
+            field.description = Some(normalize(&description, format));
+        }
+
         // Order matters for svd2html
         fields.reverse();
 
         new_registers.push(RegisterCluster::Register(Register::Single(
             RegisterInfo::builder()
                 .name(register_title.to_string())
+                .description(description)
                 .address_offset(offset)
+                .reset_value(reset.value())
+                .reset_mask(reset.mask())
                 .fields(Some(fields))
                 .build(ValidateLevel::Weak)?,
         )));
     }
 
-    if let Some(base) = alt_base {
-        base_address = base as u32;
+    if warnings {
+        check_summary(file, &summary, &new_registers);
+        check_summary_addresses(&summary, &new_registers, base_address, at);
+    }
+
+    Ok(RegisterBlock {
+        base_address: Some(base_address as u64),
+        description,
+        registers: new_registers,
+        translations: Vec::new(),
+    })
+}
+
+/// Gives the elements of `block`, written in `language`, their English description from
+/// either `block` or `other` and keeps the Chinese ones as translations. Registers and
+/// fields are matched by name.
+fn merge_translation(block: &mut RegisterBlock, other: RegisterBlock, language: Language) {
+    let mut merger = TranslationMerger {
+        language,
+        translations: Vec::new(),
+    };
+    merger.merge(&mut block.description, other.description, String::new());
+
+    for register in &mut block.registers {
+        let RegisterCluster::Register(Register::Single(register)) = register else {
+            continue;
+        };
+        let other_register = other.registers.iter().find_map(|other| match other {
+            RegisterCluster::Register(Register::Single(other))
+                if other.name.eq_ignore_ascii_case(&register.name) =>
+            {
+                Some(other)
+            }
+            _ => None,
+        });
+        merger.merge(
+            &mut register.description,
+            other_register.and_then(|other| other.description.clone()),
+            register.name.clone(),
+        );
+
+        for field in register.fields.iter_mut().flatten() {
+            let other_field = other_register
+                .and_then(|other| other.fields.as_ref())
+                .and_then(|fields| {
+                    fields.iter().find(|other| {
+                        other.name.eq_ignore_ascii_case(&field.name)
+                            && other.bit_range.offset == field.bit_range.offset
+                    })
+                });
+            let path = format!("{}.{}", register.name, field.name);
+            let from_other = merger.merge(
+                &mut field.description,
+                other_field.and_then(|other| other.description.clone()),
+                path,
+            );
+            // Value names and descriptions follow the description they were read from
+            if let Some(other_field) =
+                other_field.filter(|other| from_other && !other.enumerated_values.is_empty())
+            {
+                field.enumerated_values = other_field.enumerated_values.clone();
+            }
+        }
+    }
+
+    block.translations = merger.translations;
+}
+
+struct TranslationMerger {
+    /// Language of the block being merged into
+    language: Language,
+    translations: Vec<Translation>,
+}
+
+impl TranslationMerger {
+    /// Sets `description` to the English one of `description` and `other`, falling back to
+    /// the Chinese one. Returns whether `other` was picked.
+    fn merge(
+        &mut self,
+        description: &mut Option<String>,
+        other: Option<String>,
+        path: String,
+    ) -> bool {
+        let ours = description.take().filter(|d| !d.is_empty());
+        let other = other.filter(|d| !d.is_empty());
+        let chinese = match self.language {
+            Language::En => &other,
+            Language::ZhCn => &ours,
+        };
+        if let Some(chinese) = chinese {
+            self.translations.push(Translation {
+                path,
+                language: Language::ZhCn,
+                description: chinese.clone(),
+            });
+        }
+
+        let from_other = match self.language {
+            Language::En => ours.is_none() && other.is_some(),
+            Language::ZhCn => other.is_some(),
+        };
+        *description = if from_other { other } else { ours };
+
+        from_other
+    }
+}
+
+/// A row of the register summary table at the top of a chapter
+struct SummaryEntry {
+    name: String,
+    description: Option<String>,
+    /// Address or offset of the register, for tables with an address column
+    address: Option<(u32, Range<usize>)>,
+}
+
+/// Reads the rows of the register summary table. Names are links to the register
+/// sections, `` `utx_config`_ ``.
+/// Entries of the register summary table, passing addresses that don't parse to `bad_address`
+fn summary_table(
+    table: Pair<Rule>,
+    mut bad_address: impl FnMut(LiteralError),
+) -> Result<Vec<SummaryEntry>> {
+    let table = GridTable::parse(table.as_str(), table.as_span().start())?;
+    let name_column = table.column(&["Name", "Register", "Register name"]);
+    let description_column = table.column(&["Description"]);
+    let address_column = table.column(&["Address", "Offset", "Address offset"]);
+    if name_column.is_none() {
+        return Err(anyhow!("No name column in {:?}", table.header));
+    }
+
+    let mut entries = Vec::<SummaryEntry>::new();
+    for row in &table.rows {
+        let name = row.text(name_column);
+        let description = row.text(description_column);
+        // Rows without a name continue the description of the register above
+        if name.is_empty() {
+            if let Some(entry) = entries.last_mut().filter(|_| !description.is_empty()) {
+                entry.description = Some(match entry.description.take() {
+                    Some(previous) => format!("{}\n{}", previous, description),
+                    None => description.to_string(),
+                });
+            }
+            continue;
+        }
+
+        let span = row.span(address_column);
+        let address = match row.text(address_column) {
+            "" => None,
+            address => match parse_literal(address, 16) {
+                Ok(address) => Some(address.value as u32),
+                Err(e) => {
+                    bad_address(e.offset(span.start));
+                    None
+                }
+            },
+        };
+        entries.push(SummaryEntry {
+            name: name.trim_end_matches('_').trim_matches('`').to_string(),
+            description: (!description.is_empty()).then(|| description.to_string()),
+            address: address.map(|address| (address, span)),
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Columns of a register's field table, found by their header
+struct FieldColumns {
+    bits: Option<usize>,
+    name: Option<usize>,
+    access: Option<usize>,
+    reset: Option<usize>,
+    description: Option<usize>,
+}
+
+impl FieldColumns {
+    fn find(table: &GridTable) -> Result<FieldColumns> {
+        let columns = FieldColumns {
+            bits: table.column(&["Bit", "Bits"]),
+            name: table.column(&["Name", "Field", "Field name"]),
+            access: table.column(&["Type", "Access", "Attribute"]),
+            reset: table.column(&["Reset", "Reset value", "Default"]),
+            description: table.column(&["Description"]),
+        };
+        if columns.bits.is_none() || columns.name.is_none() {
+            return Err(anyhow!("No bit or name column in {:?}", table.header));
+        }
+
+        Ok(columns)
+    }
+}
+
+/// Parses the bit column of a field row, `31:16`, `[7:0]` or `5`
+fn parse_bits(text: &str) -> Result<BitRange> {
+    let bits = text.trim().trim_start_matches('[').trim_end_matches(']');
+    let (msb, lsb) = bits.split_once(':').unwrap_or((bits, bits));
+    let msb: u32 = msb
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid bits: {}", text))?;
+    let lsb: u32 = lsb
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid bits: {}", text))?;
+    if msb < lsb {
+        return Err(anyhow!("Invalid bits: {}", text));
+    }
+
+    Ok(BitRange::from_msb_lsb(msb, lsb))
+}
+
+/// Reports registers the summary table lists without a register section, and the reverse
+fn check_summary(file: &Path, summary: &[SummaryEntry], registers: &[RegisterCluster]) {
+    let sections: Vec<&str> = registers
+        .iter()
+        .map(|r| match r {
+            RegisterCluster::Register(register) => register.name.as_str(),
+            RegisterCluster::Cluster(cluster) => cluster.name.as_str(),
+        })
+        .collect();
+
+    for entry in summary {
+        if !sections
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(&entry.name))
+        {
+            println!(
+                "Warning: {}: register '{}' is listed in the summary table but has no register section",
+                file.display(),
+                entry.name
+            );
+        }
     }
+    for name in sections {
+        if !summary
+            .iter()
+            .any(|entry| entry.name.eq_ignore_ascii_case(name))
+        {
+            println!(
+                "Warning: {}: register '{}' has a register section but isn't listed in the summary table",
+                file.display(),
+                name
+            );
+        }
+    }
+}
 
-    Ok(PeripheralInfo::builder()
-        .name(peripheral_name)
-        .registers(Some(new_registers))
-        .base_address(base_address as u64)
-        .build(ValidateLevel::Strict)?)
+/// Checks the addresses of the summary table against the addresses of the register
+/// sections. Summary tables give either the address or the offset of a register.
+fn check_summary_addresses(
+    summary: &[SummaryEntry],
+    registers: &[RegisterCluster],
+    base_address: u32,
+    at: impl Fn(Range<usize>, String) -> Diagnostic,
+) {
+    for entry in summary {
+        let Some((address, span)) = &entry.address else {
+            continue;
+        };
+        let Some(register) = registers.iter().find_map(|r| match r {
+            RegisterCluster::Register(register)
+                if register.name.eq_ignore_ascii_case(&entry.name) =>
+            {
+                Some(register)
+            }
+            _ => None,
+        }) else {
+            continue;
+        };
+        let offset = register.address_offset;
+        if *address != offset && *address != base_address + offset {
+            let message = format!(
+                "The summary table puts register '{}' at {:#x}, its register section at {:#x}",
+                entry.name,
+                address,
+                base_address + offset
+            );
+            println!("{}", at(span.clone(), message).warning().render());
+        }
+    }
 }
 
-fn parse_hex_or_zero(input: &str) -> u32 {
-    if input.is_empty() {
-        return 0;
+/// Text of the paragraphs in a stretch of the document, like the introduction or the text
+/// between a register's address and its table. Directives like `.. figure::` are dropped
+/// with their indented options, paragraphs stay separated by a blank line.
+fn prose(text: &str) -> Option<String> {
+    let mut lines = Vec::<&str>::new();
+    let mut in_directive = false;
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("..") {
+            in_directive = true;
+            continue;
+        }
+        // Directive options and content are indented below the directive
+        if in_directive && (line.starts_with(char::is_whitespace) || trimmed.is_empty()) {
+            if trimmed.is_empty() {
+                in_directive = false;
+            }
+            continue;
+        }
+        in_directive = false;
+        if !trimmed.is_empty() || lines.last().is_some_and(|last| !last.is_empty()) {
+            lines.push(trimmed);
+        }
+    }
+    if lines.last().is_some_and(|last| last.is_empty()) {
+        lines.pop();
     }
 
-    if input.find('\'').is_some() {
-        let input2 = &input[input.find('\'').unwrap() + 2..];
-        u32::from_str_radix(input2, 16).expect("Error unwrapping string from hex")
-    } else {
-        u32::from_str_radix(input, 16).expect("Error unwrapping string from hex")
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+/// Bytes of the first line of `pair`
+fn first_line(pair: &Pair<Rule>) -> Range<usize> {
+    let start = pair.as_span().start();
+    start..start + pair.as_str().lines().next().map_or(0, str::len)
+}
+
+/// Parses the reset column of a field row. Empty cells have no reset value, bare numbers
+/// are hex.
+fn parse_reset_value(input: &str) -> Result<Option<Literal>, LiteralError> {
+    if input.trim().is_empty() {
+        return Ok(None);
     }
+
+    parse_literal(input, 16).map(Some)
 }
 
-fn parse_u32_or_zero(input: &str) -> u32 {
-    if input.is_empty() {
-        return 0;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UART_RST: &str = "\
+Register description
+====================
+
++---------------+-------------+-----------------+
+| Name          | Address     | Description     |
++===============+=============+=================+
+| `utx_config`_ | 0x2000a000  | TX config       |
++---------------+-------------+-----------------+
+| `urx_config`_ | 4           | RX config       |
++---------------+-------------+-----------------+
+
+utx_config
+----------
+
+**Address：**  0x2000a000
+
++------+-------------+------+-------+-------------+
+| Bit  | Name        | Type | Reset | Description |
++======+=============+======+=======+=============+
+| 0    | cr_utx_en   | r/w  | 1'b0  | Enable      |
++------+-------------+------+-------+-------------+
+
+urx_config
+----------
+
+**Address：**  0x2000a004
+
++------+-------------+------+-------+-------------+
+| Bit  | Name        | Type | Reset | Description |
++======+=============+======+=======+=============+
+| 0    | cr_urx_en   | r/w  | 1'b0  | Enable      |
++------+-------------+------+-------+-------------+
+";
+
+    fn summary_pair(text: &str) -> Pair<'_, Rule> {
+        DocsRegParser::parse(Rule::PERIPHERAL_FILE, text)
+            .unwrap()
+            .next()
+            .unwrap()
+            .into_inner()
+            .nth(2)
+            .unwrap()
+    }
+
+    fn summary(text: &str) -> Result<Vec<SummaryEntry>> {
+        summary_table(summary_pair(text), |e| panic!("{}", e))
+    }
+
+    #[test]
+    fn summary_addresses() {
+        let entries = summary(UART_RST).unwrap();
+        let names: Vec<_> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["utx_config", "urx_config"]);
+        let addresses: Vec<_> = entries.iter().map(|e| e.address.clone().unwrap()).collect();
+        assert_eq!(addresses[0].0, 0x2000_a000);
+        assert_eq!(addresses[1].0, 4);
+        assert_eq!(&UART_RST[addresses[1].1.clone()], "4");
+    }
+
+    #[test]
+    fn summary_without_addresses() {
+        let text = UART_RST.replace("| Address     |", "| Offset      |");
+        assert!(summary(&text).unwrap().iter().all(|e| e.address.is_some()));
+
+        let text = UART_RST.replace("Address     |", "Range       |");
+        assert!(summary(&text).unwrap().iter().all(|e| e.address.is_none()));
     }
 
-    input.parse::<u32>().unwrap()
+    #[test]
+    fn registers() {
+        let block = parse_doc_rst(
+            Path::new("uart.rst"),
+            UART_RST,
+            DescriptionFormat::Plain,
+            true,
+        )
+        .unwrap();
+        assert_eq!(block.base_address, Some(0x2000_a000));
+        let offsets: Vec<_> = block
+            .registers
+            .iter()
+            .map(|r| match r {
+                RegisterCluster::Register(r) => (r.name.as_str(), r.address_offset),
+                RegisterCluster::Cluster(c) => (c.name.as_str(), c.address_offset),
+            })
+            .collect();
+        assert_eq!(offsets, [("utx_config", 0), ("urx_config", 4)]);
+    }
+
+    #[test]
+    fn registers_before_the_first() {
+        let text = UART_RST.replace("0x2000a004", "0x20009ffc");
+        let error = parse_doc_rst(Path::new("uart.rst"), &text, DescriptionFormat::Plain, true)
+            .unwrap_err()
+            .downcast::<Diagnostic>()
+            .unwrap();
+        // At the register heading
+        assert_eq!((error.line, error.column, error.length), (23, 1, 10));
+        assert_eq!(
+            error.message,
+            "Register 'urx_config' at 0x20009ffc comes before the first register of the chapter at 0x2000a000"
+        );
+    }
+
+    #[test]
+    fn bad_summary_address() {
+        let text = UART_RST.replace("| 4           |", "| 4g          |");
+        let mut errors = Vec::new();
+        let entries = summary_table(summary_pair(&text), |e| errors.push(e)).unwrap();
+        assert!(entries[0].address.is_some());
+        assert!(entries[1].address.is_none());
+        // At the invalid digit
+        assert_eq!(errors.len(), 1);
+        assert_eq!(&text[errors[0].span()], "g");
+
+        // The registers are still read from their sections
+        let block =
+            parse_doc_rst(Path::new("uart.rst"), &text, DescriptionFormat::Plain, true).unwrap();
+        assert_eq!(block.registers.len(), 2);
+    }
 }
@@ -0,0 +1,39 @@
+use anyhow::Result;
+use svd_rs::{RegisterCluster, ValidateLevel};
+
+use crate::manifest::Language;
+
+/// Registers read from a single source, with offsets relative to the peripheral base
+#[derive(Debug, Clone, Default)]
+pub struct RegisterBlock {
+    /// Base address documented by the source itself, if it has one
+    pub base_address: Option<u64>,
+    /// Description of the peripheral, if the source has one
+    pub description: Option<String>,
+    pub registers: Vec<RegisterCluster>,
+    /// Descriptions in a second language, SVD has room for only one
+    pub translations: Vec<Translation>,
+}
+
+/// Description of an element in a language other than the one of its `<description>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Translation {
+    /// `register` or `register.field`, empty for the peripheral itself. The device
+    /// prefixes it with the peripheral name.
+    pub path: String,
+    pub language: Language,
+    pub description: String,
+}
+
+/// Anything register definitions can be read from.
+///
+/// The C header and RST parsers implement this, other tools can implement it to feed
+/// extra registers into a [`DeviceAssembler`](crate::DeviceAssembler).
+pub trait RegisterSource {
+    fn load(&self) -> Result<RegisterBlock>;
+
+    /// How strictly the peripheral built from these registers is validated
+    fn validate_level(&self) -> ValidateLevel {
+        ValidateLevel::Weak
+    }
+}
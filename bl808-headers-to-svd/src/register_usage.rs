@@ -0,0 +1,557 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use svd_rs::{array::names, Cluster, Peripheral, Register, RegisterCluster};
+
+use crate::c_struct::{tokenize, Token, TokenKind};
+use crate::diagnostic::in_file;
+
+/// Driver macros taking a register, `BL_RD_REG(GLB_BASE, GLB_SOC_INFO0)`, and the
+/// position of the register argument
+const REGISTER_MACROS: [(&str, usize); 2] = [("BL_RD_REG", 1), ("BL_WR_REG", 1)];
+
+/// Driver macros taking a field, `BL_SET_REG_BITS_VAL(tmpVal, GLB_REG_EN, 1)`, and the
+/// position of the field argument
+const FIELD_MACROS: [(&str, usize); 5] = [
+    ("BL_SET_REG_BITS_VAL", 1),
+    ("BL_GET_REG_BITS_VAL", 1),
+    ("BL_SET_REG_BIT", 1),
+    ("BL_CLR_REG_BIT", 1),
+    ("BL_IS_REG_BIT_SET", 1),
+];
+
+/// A register or field access of a driver that the model doesn't have
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnresolvedAccess {
+    /// Macro or member name as the driver writes it, `GLB_SOC_INFO0` or `->soc_info0`
+    pub name: String,
+    pub file: PathBuf,
+    pub line: usize,
+}
+
+/// How the SDK drivers use the registers of the model
+#[derive(Debug, Clone, Default)]
+pub struct RegisterUsage {
+    /// Accesses to registers and fields the model lacks, the first one of each name
+    pub missing: Vec<UnresolvedAccess>,
+    /// `(peripheral, register)` pairs no driver accesses
+    pub unused: Vec<(String, String)>,
+}
+
+/// Registers of the model, by upper case name
+struct RegisterIndex {
+    peripherals: Vec<IndexedPeripheral>,
+    /// `(peripheral, register)` indices of the registers accessed
+    used: HashSet<(usize, usize)>,
+}
+
+struct IndexedPeripheral {
+    /// Prefixes of the macros of the peripheral, `UART0_` and `UART_` for UART0
+    prefixes: Vec<String>,
+    registers: Vec<IndexedRegister>,
+}
+
+struct IndexedRegister {
+    /// Path of the register in the model, `CH[%s].CONFIG` for registers of clusters
+    path: String,
+    /// Names the drivers may use for the register, one per array element and cluster
+    /// prefix, `CONFIG`, `CH0_CONFIG`, `CH1_CONFIG`
+    names: Vec<String>,
+    fields: Vec<String>,
+}
+
+impl RegisterIndex {
+    fn new(peripherals: &[Peripheral], macro_prefixes: &HashMap<String, String>) -> RegisterIndex {
+        let peripherals = peripherals
+            .iter()
+            .map(|p| {
+                let name = p.name.to_ascii_uppercase();
+                let mut prefixes = vec![format!("{}_", name)];
+                let instance = name.trim_end_matches(|c: char| c.is_ascii_digit());
+                if instance != name {
+                    prefixes.push(format!("{}_", instance));
+                }
+                if let Some(prefix) = macro_prefixes.get(&p.name) {
+                    let prefix = format!("{}_", prefix.to_ascii_uppercase());
+                    if !prefixes.contains(&prefix) {
+                        prefixes.push(prefix);
+                    }
+                }
+
+                let mut registers = Vec::new();
+                let children = p.registers.as_deref().unwrap_or_default();
+                index_registers(children, &[String::new()], "", &mut registers);
+                IndexedPeripheral {
+                    prefixes,
+                    registers,
+                }
+            })
+            .collect();
+
+        RegisterIndex {
+            peripherals,
+            used: HashSet::new(),
+        }
+    }
+
+    /// Marks the registers `macro_name` refers to. `None` if no peripheral has its
+    /// prefix, otherwise whether any register matched.
+    fn register(&mut self, macro_name: &str) -> Option<bool> {
+        let macro_name = macro_name.to_ascii_uppercase();
+        let mut any_peripheral = false;
+        let mut found = false;
+
+        for (p, peripheral) in self.peripherals.iter().enumerate() {
+            for prefix in &peripheral.prefixes {
+                let Some(register) = macro_name.strip_prefix(prefix.as_str()) else {
+                    continue;
+                };
+                any_peripheral = true;
+                for (r, indexed) in peripheral.registers.iter().enumerate() {
+                    if indexed.names.iter().any(|name| name == register) {
+                        self.used.insert((p, r));
+                        found = true;
+                    }
+                }
+            }
+        }
+
+        any_peripheral.then_some(found)
+    }
+
+    /// Marks the registers with the field `macro_name`, like [`RegisterIndex::register`].
+    /// Header fields sometimes keep the prefix, `GLB_ID` is `glb_id`.
+    fn field(&mut self, macro_name: &str) -> Option<bool> {
+        let macro_name = macro_name.to_ascii_uppercase();
+        let mut any_peripheral = false;
+        let mut found = false;
+
+        for (p, peripheral) in self.peripherals.iter().enumerate() {
+            for prefix in &peripheral.prefixes {
+                let Some(field) = macro_name.strip_prefix(prefix.as_str()) else {
+                    continue;
+                };
+                any_peripheral = true;
+                for (r, indexed) in peripheral.registers.iter().enumerate() {
+                    if indexed
+                        .fields
+                        .iter()
+                        .any(|f| f == field || *f == macro_name)
+                    {
+                        self.used.insert((p, r));
+                        found = true;
+                    }
+                }
+            }
+        }
+
+        any_peripheral.then_some(found)
+    }
+
+    /// Marks the registers called `name` in any peripheral, for `->name.BF` accesses
+    /// that don't say which peripheral they go to
+    fn member(&mut self, name: &str) -> bool {
+        let name = name.to_ascii_uppercase();
+        let mut found = false;
+
+        for (p, peripheral) in self.peripherals.iter().enumerate() {
+            for (r, indexed) in peripheral.registers.iter().enumerate() {
+                if indexed.names.contains(&name) {
+                    self.used.insert((p, r));
+                    found = true;
+                }
+            }
+        }
+
+        found
+    }
+}
+
+/// Adds the registers of `children` and of the clusters among them to `registers`.
+/// `prefixes` are the cluster prefixes the driver names may carry, `path` the cluster
+/// path of the model.
+fn index_registers(
+    children: &[RegisterCluster],
+    prefixes: &[String],
+    path: &str,
+    registers: &mut Vec<IndexedRegister>,
+) {
+    for child in children {
+        match child {
+            RegisterCluster::Register(register) => {
+                let instances = match register {
+                    Register::Single(info) => vec![info.name.clone()],
+                    Register::Array(info, dim) => names(info, dim).collect(),
+                };
+                let names = prefixes
+                    .iter()
+                    .flat_map(|prefix| instances.iter().map(move |n| prefix.clone() + n))
+                    .map(|name| name.to_ascii_uppercase())
+                    .collect();
+                let fields = register
+                    .fields()
+                    .map(|f| f.name.to_ascii_uppercase())
+                    .collect();
+                registers.push(IndexedRegister {
+                    path: format!("{}{}", path, register.name),
+                    names,
+                    fields,
+                });
+            }
+            RegisterCluster::Cluster(cluster) => {
+                let instances = match cluster {
+                    Cluster::Single(info) => vec![info.name.clone()],
+                    Cluster::Array(info, dim) => names(info, dim).collect(),
+                };
+                // Drivers use cluster registers with and without the cluster prefix
+                let mut cluster_prefixes = prefixes.to_vec();
+                for prefix in prefixes {
+                    for instance in &instances {
+                        cluster_prefixes.push(format!("{}{}_", prefix, instance));
+                    }
+                }
+                let path = format!("{}{}.", path, cluster.name);
+                index_registers(&cluster.children, &cluster_prefixes, &path, registers);
+            }
+        }
+    }
+}
+
+/// Scans the driver `sources` for register accesses and resolves them against
+/// `peripherals`. Accesses are:
+///
+/// - register macros, `BL_RD_REG(GLB_BASE, GLB_SOC_INFO0)` and `BL_WR_REG`
+/// - field macros, `BL_SET_REG_BITS_VAL(tmpVal, GLB_REG_EN, 1)` and friends
+/// - offset defines, `getreg32(reg_base + UART_UTX_CONFIG_OFFSET)`
+/// - struct members, `GLB->soc_info0.BF.glb_id` or `->soc_info0.WORD`
+///
+/// Macros start with the peripheral name or, for peripherals the SDK calls differently,
+/// with their entry in `macro_prefixes`, see [`Manifest::macro_prefixes`].
+///
+/// [`Manifest::macro_prefixes`]: crate::Manifest::macro_prefixes
+pub fn register_usage(
+    peripherals: &[Peripheral],
+    macro_prefixes: &HashMap<String, String>,
+    sources: &[PathBuf],
+) -> Result<RegisterUsage> {
+    scan_sources(peripherals, macro_prefixes, sources, |file| {
+        fs::read_to_string(file).with_context(|| format!("Error reading file: {}", file.display()))
+    })
+}
+
+/// [`register_usage`] with the sources read by `read`
+fn scan_sources(
+    peripherals: &[Peripheral],
+    macro_prefixes: &HashMap<String, String>,
+    sources: &[PathBuf],
+    read: impl Fn(&Path) -> Result<String>,
+) -> Result<RegisterUsage> {
+    let mut index = RegisterIndex::new(peripherals, macro_prefixes);
+    let mut usage = RegisterUsage::default();
+    let mut missing_names = HashSet::new();
+
+    for file in sources {
+        let source = read(file)?;
+        let tokens: Vec<Token> = tokenize(&source)
+            .map_err(|e| in_file(e, file))?
+            .into_iter()
+            .filter(|t| !matches!(t.kind, TokenKind::Comment | TokenKind::Preprocessor))
+            .collect();
+
+        let mut report = |name: String, line: usize| {
+            if missing_names.insert(name.clone()) {
+                usage.missing.push(UnresolvedAccess {
+                    name,
+                    file: file.clone(),
+                    line,
+                });
+            }
+        };
+
+        for (i, token) in tokens.iter().enumerate() {
+            if token.kind != TokenKind::Identifier {
+                if token.is("-") && tokens.get(i + 1).is_some_and(|t| t.is(">")) {
+                    // ->name.BF / ->name.WORD
+                    let member = &tokens[i + 2..];
+                    if let [name, dot, word, ..] = member {
+                        if name.kind == TokenKind::Identifier
+                            && dot.is(".")
+                            && ["BF", "WORD", "SHORT", "BYTE"].contains(&word.text)
+                            && !index.member(name.text)
+                        {
+                            report(format!("->{}", name.text), name.line);
+                        }
+                    }
+                }
+                continue;
+            }
+
+            if let Some(register) = token.text.strip_suffix("_OFFSET") {
+                if index.register(register) == Some(false) {
+                    report(token.text.to_string(), token.line);
+                }
+                continue;
+            }
+
+            let register_arg = REGISTER_MACROS
+                .iter()
+                .find(|(name, _)| *name == token.text)
+                .map(|(_, arg)| (*arg, true));
+            let field_arg = FIELD_MACROS
+                .iter()
+                .find(|(name, _)| *name == token.text)
+                .map(|(_, arg)| (*arg, false));
+            let Some((arg, is_register)) = register_arg.or(field_arg) else {
+                continue;
+            };
+            let Some(argument) = macro_argument(&tokens[i + 1..], arg) else {
+                continue;
+            };
+
+            let resolved = if is_register {
+                index.register(argument.text)
+            } else {
+                index.field(argument.text)
+            };
+            // Only registers name their peripheral through the base argument, unknown
+            // field prefixes are usually local macros
+            if resolved == Some(false) || (is_register && resolved.is_none()) {
+                report(argument.text.to_string(), argument.line);
+            }
+        }
+    }
+
+    for (p, (peripheral, indexed)) in peripherals.iter().zip(&index.peripherals).enumerate() {
+        for (r, register) in indexed.registers.iter().enumerate() {
+            if !index.used.contains(&(p, r)) {
+                usage
+                    .unused
+                    .push((peripheral.name.clone(), register.path.clone()));
+            }
+        }
+    }
+
+    Ok(usage)
+}
+
+/// The macro argument at `position` if it's a single identifier. `tokens` start at the
+/// opening parenthesis.
+fn macro_argument<'a>(tokens: &[Token<'a>], position: usize) -> Option<Token<'a>> {
+    if !tokens.first()?.is("(") {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut argument = 0;
+    let mut argument_tokens = Vec::new();
+    for token in &tokens[1..] {
+        if token.is("(") {
+            depth += 1;
+        } else if token.is(")") {
+            if depth == 0 {
+                break;
+            }
+            depth -= 1;
+        } else if token.is(",") && depth == 0 {
+            argument += 1;
+            continue;
+        }
+        if argument == position {
+            argument_tokens.push(*token);
+        }
+    }
+
+    match argument_tokens[..] {
+        [token] if token.kind == TokenKind::Identifier => Some(token),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use svd_rs::{
+        ClusterInfo, DimElement, Field, FieldInfo, PeripheralInfo, RegisterInfo, ValidateLevel,
+    };
+
+    fn register(name: &str, offset: u32, fields: &[&str]) -> RegisterInfo {
+        let fields = fields
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                Field::Single(
+                    FieldInfo::builder()
+                        .name(name.to_string())
+                        .bit_offset(i as u32)
+                        .bit_width(1)
+                        .build(ValidateLevel::Weak)
+                        .unwrap(),
+                )
+            })
+            .collect();
+        RegisterInfo::builder()
+            .name(name.to_string())
+            .address_offset(offset)
+            .fields(Some(fields))
+            .build(ValidateLevel::Weak)
+            .unwrap()
+    }
+
+    fn dim(count: u32, increment: u32) -> DimElement {
+        DimElement::builder()
+            .dim(count)
+            .dim_increment(increment)
+            .build(ValidateLevel::Weak)
+            .unwrap()
+    }
+
+    /// DMA with a top level register, a register array and a cluster array of channels
+    fn dma() -> Peripheral {
+        let channel = ClusterInfo::builder()
+            .name("C%s".to_string())
+            .address_offset(0x100)
+            .children(vec![
+                RegisterCluster::Register(Register::Single(register("CONFIG", 0, &["E"]))),
+                RegisterCluster::Register(Register::Single(register("LLI", 4, &[]))),
+            ])
+            .build(ValidateLevel::Weak)
+            .unwrap();
+        let registers = vec![
+            RegisterCluster::Register(Register::Single(register("INTSTATUS", 0, &["STATUS"]))),
+            RegisterCluster::Register(Register::Array(register("SOFT[%s]", 0x20, &[]), dim(2, 4))),
+            RegisterCluster::Cluster(Cluster::Array(channel, dim(2, 0x100))),
+        ];
+        Peripheral::Single(
+            PeripheralInfo::builder()
+                .name("DMA0".to_string())
+                .base_address(0x2000_c000)
+                .registers(Some(registers))
+                .build(ValidateLevel::Weak)
+                .unwrap(),
+        )
+    }
+
+    fn scan(source: &str) -> RegisterUsage {
+        let source = source.to_string();
+        scan_sources(&[dma()], &HashMap::new(), &[PathBuf::from("dma.c")], |_| {
+            Ok(source.clone())
+        })
+        .unwrap()
+    }
+
+    fn missing(usage: &RegisterUsage) -> Vec<(&str, usize)> {
+        usage
+            .missing
+            .iter()
+            .map(|access| (access.name.as_str(), access.line))
+            .collect()
+    }
+
+    #[test]
+    fn accesses() {
+        let usage = scan(
+            "tmp = BL_RD_REG(DMA_BASE, DMA_INTSTATUS);\n\
+             tmp = BL_GET_REG_BITS_VAL(tmp, DMA_STATUS);\n\
+             BL_WR_REG(DMA_BASE, DMA_NOPE, tmp);\n\
+             tmp = BL_SET_REG_BITS_VAL(tmp, DMA_NOPE_EN, 1);\n\
+             tmp = BL_SET_REG_BITS_VAL(tmp, LOCAL_EN, 1);\n\
+             BL_WR_REG(SPI_BASE, SPI_CONFIG, tmp);\n",
+        );
+        assert_eq!(
+            missing(&usage),
+            [("DMA_NOPE", 3), ("DMA_NOPE_EN", 4), ("SPI_CONFIG", 6)]
+        );
+        assert!(!usage.unused.iter().any(|(_, r)| r == "INTSTATUS"));
+    }
+
+    #[test]
+    fn register_arrays_are_expanded() {
+        let usage =
+            scan("getreg32(base + DMA_SOFT1_OFFSET);\ngetreg32(base + DMA_SOFT2_OFFSET);\n");
+        assert_eq!(missing(&usage), [("DMA_SOFT2_OFFSET", 2)]);
+        assert!(!usage.unused.iter().any(|(_, r)| r == "SOFT[%s]"));
+    }
+
+    #[test]
+    fn cluster_registers() {
+        // With and without the cluster prefix, fields of cluster registers included
+        let usage = scan(
+            "getreg32(base + DMA_C1_LLI_OFFSET);\n\
+             tmp = BL_SET_REG_BITS_VAL(tmp, DMA_E, 1);\n\
+             getreg32(base + DMA_C2_LLI_OFFSET);\n",
+        );
+        assert_eq!(missing(&usage), [("DMA_C2_LLI_OFFSET", 3)]);
+        let unused: Vec<_> = usage.unused.iter().map(|(_, r)| r.as_str()).collect();
+        assert_eq!(unused, ["INTSTATUS", "SOFT[%s]"]);
+
+        let members = scan("DMA->config.BF.e = 1;\nDMA->lli.WORD = 0;\n");
+        assert!(members.missing.is_empty());
+    }
+
+    #[test]
+    fn unused_registers_by_path() {
+        let usage = scan("");
+        let unused: Vec<_> = usage
+            .unused
+            .iter()
+            .map(|(p, r)| format!("{}.{}", p, r))
+            .collect();
+        assert_eq!(
+            unused,
+            [
+                "DMA0.INTSTATUS",
+                "DMA0.SOFT[%s]",
+                "DMA0.C%s.CONFIG",
+                "DMA0.C%s.LLI"
+            ]
+        );
+    }
+
+    #[test]
+    fn renamed_peripherals() {
+        let hbn = Peripheral::Single(
+            PeripheralInfo::builder()
+                .name("LowPower".to_string())
+                .base_address(0x2000_f000)
+                .registers(Some(vec![RegisterCluster::Register(Register::Single(
+                    register("HBN_CTL", 0, &["HBN_MODE"]),
+                ))]))
+                .build(ValidateLevel::Weak)
+                .unwrap(),
+        );
+        let source = "tmp = BL_RD_REG(HBN_BASE, HBN_HBN_CTL);\n\
+                      tmp = BL_SET_REG_BITS_VAL(tmp, HBN_MODE, 1);\n\
+                      tmp = BL_RD_REG(HBN_BASE, HBN_GLB);\n";
+        let scan = |prefixes: &HashMap<String, String>| {
+            scan_sources(
+                std::slice::from_ref(&hbn),
+                prefixes,
+                &[PathBuf::from("hbn.c")],
+                |_| Ok(source.to_string()),
+            )
+            .unwrap()
+        };
+
+        // Without the prefix every access is missing
+        let usage = scan(&HashMap::new());
+        assert_eq!(missing(&usage), [("HBN_HBN_CTL", 1), ("HBN_GLB", 3)]);
+
+        let prefixes = HashMap::from([("LowPower".to_string(), "HBN".to_string())]);
+        let usage = scan(&prefixes);
+        assert_eq!(missing(&usage), [("HBN_GLB", 3)]);
+        assert!(usage.unused.is_empty());
+    }
+
+    #[test]
+    fn macro_arguments() {
+        let tokens = tokenize("(a, f(b, c), d)").unwrap();
+        assert_eq!(macro_argument(&tokens, 0).unwrap().text, "a");
+        assert!(macro_argument(&tokens, 1).is_none());
+        assert_eq!(macro_argument(&tokens, 2).unwrap().text, "d");
+        assert!(macro_argument(&tokens, 3).is_none());
+    }
+}
@@ -0,0 +1,40 @@
+use svd_rs::BitRange;
+
+use crate::literal::Literal;
+
+/// Register reset value and mask, assembled from the reset values of its fields
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct RegisterReset {
+    value: u64,
+    mask: u64,
+}
+
+impl RegisterReset {
+    /// Adds the reset value of the field at `bit_range`. Don't care bits of `reset` are
+    /// left out of the mask. Returns false, and leaves the register untouched, if `reset`
+    /// doesn't fit in the field.
+    pub fn add_field(&mut self, bit_range: BitRange, reset: &Literal) -> bool {
+        if !reset.fits(bit_range.width) {
+            return false;
+        }
+        let field_mask = if bit_range.width >= 64 {
+            u64::MAX
+        } else {
+            (1u64 << bit_range.width) - 1
+        };
+
+        self.value |= reset.value << bit_range.offset;
+        self.mask |= (field_mask & !reset.dont_care) << bit_range.offset;
+        true
+    }
+
+    /// Reset value, if any field had a known reset value
+    pub fn value(&self) -> Option<u64> {
+        (self.mask != 0).then_some(self.value)
+    }
+
+    /// Bits of the register whose reset value is known
+    pub fn mask(&self) -> Option<u64> {
+        (self.mask != 0).then_some(self.mask)
+    }
+}
@@ -0,0 +1,302 @@
+//! Turns reStructuredText from the reference manual into clean descriptions: inline markup
+//! is converted, lines wrapped to fit a table cell are joined again and list lines, like
+//! the values of a field, stay on lines of their own.
+
+use crate::enumerated_values::is_enumeration_line;
+
+/// How inline markup ends up in descriptions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DescriptionFormat {
+    /// Markup is dropped, `**IR** mode` becomes `IR mode`
+    #[default]
+    Plain,
+    /// Markup is converted to Markdown and list lines become list items
+    Markdown,
+}
+
+/// Normalizes the RST text `text` to `format`
+pub fn normalize(text: &str, format: DescriptionFormat) -> String {
+    let mut lines = Vec::<String>::new();
+    let mut paragraph_start = true;
+
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() {
+            // Paragraphs are separated by a blank line
+            if !paragraph_start {
+                lines.push(String::new());
+            }
+            paragraph_start = true;
+            continue;
+        }
+
+        if paragraph_start || is_list_line(&line) {
+            lines.push(line);
+        } else if let Some(previous) = lines.last_mut() {
+            previous.push(' ');
+            previous.push_str(&line);
+        }
+        paragraph_start = false;
+    }
+    if lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+
+    lines
+        .iter()
+        .map(|line| {
+            let line = inline(line, format);
+            if format == DescriptionFormat::Markdown && is_enumeration_line(&line) {
+                format!("- {}", line)
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Lines that start a list item: bullets, enumerators and field values like `2'd0 : Off`
+fn is_list_line(line: &str) -> bool {
+    if ["- ", "* ", "+ ", "• "]
+        .iter()
+        .any(|bullet| line.starts_with(bullet))
+    {
+        return true;
+    }
+
+    // 1. 1) (1) a. a)
+    let enumerator = line.trim_start_matches('(');
+    let label = enumerator.len() - enumerator.trim_start_matches(char::is_alphanumeric).len();
+    let rest = &enumerator[label..];
+    let is_enumerator = (label == 1
+        || (label == 2 && enumerator[..label].chars().all(|c| c.is_ascii_digit())))
+        && (rest.starts_with(". ") || rest.starts_with(") "));
+
+    is_enumerator || is_enumeration_line(line)
+}
+
+/// Converts the inline markup of a line
+fn inline(line: &str, format: DescriptionFormat) -> String {
+    let markdown = format == DescriptionFormat::Markdown;
+    let chars: Vec<char> = line.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        // Backslash escapes, inline literals below keep theirs
+        if c == '\\' && i + 1 < chars.len() {
+            out.push(chars[i + 1]);
+            i += 2;
+            continue;
+        }
+        if !can_start(&chars, i) {
+            out.push(c);
+            i += 1;
+            continue;
+        }
+
+        // :role:`text`
+        let role_end = role(&chars, i);
+        let start = role_end.unwrap_or(i);
+        let markup = match &chars[start..] {
+            ['`', '`', ..] => Some(("``", "``")),
+            ['*', '*', ..] => Some(("**", "**")),
+            ['*', ..] => Some(("*", "*")),
+            ['`', ..] => Some(("`", "`")),
+            ['|', ..] if role_end.is_none() => Some(("|", "|")),
+            _ => None,
+        };
+        let Some((open, close)) = markup else {
+            out.push(c);
+            i += 1;
+            continue;
+        };
+        let inner_start = start + open.len();
+        let literal = open == "``";
+        let Some(inner_end) = find_end(&chars, inner_start, close, !literal) else {
+            out.push(c);
+            i += 1;
+            continue;
+        };
+        let inner = &chars[inner_start..inner_end];
+        let inner: String = if literal {
+            inner.iter().collect()
+        } else {
+            unescape(inner)
+        };
+        let mut end = inner_end + close.len();
+        // Hyperlink and substitution references end with `_` or `__`
+        let mut reference = false;
+        while end < chars.len() && chars[end] == '_' && end - inner_end - close.len() < 2 {
+            reference = true;
+            end += 1;
+        }
+
+        match (open, markdown) {
+            ("``", true) => out.push_str(&format!("`{}`", inner)),
+            ("**", true) => out.push_str(&format!("**{}**", inner)),
+            ("*", true) => out.push_str(&format!("*{}*", inner)),
+            ("`", _) if reference => out.push_str(&link(&inner, markdown)),
+            ("`", true) => out.push_str(&format!("`{}`", inner)),
+            _ => out.push_str(&inner),
+        }
+        i = end;
+    }
+
+    out
+}
+
+/// Text of a hyperlink reference, `` `text <url>`_ ``
+fn link(inner: &str, markdown: bool) -> String {
+    match inner
+        .strip_suffix('>')
+        .and_then(|rest| rest.rsplit_once('<'))
+    {
+        Some((text, url)) if markdown => format!("[{}]({})", text.trim(), url),
+        Some((text, _)) => text.trim().to_string(),
+        None => inner.to_string(),
+    }
+}
+
+/// End of the `:role:` prefix at `i`, if there is one followed by a backtick
+fn role(chars: &[char], i: usize) -> Option<usize> {
+    if chars[i] != ':' {
+        return None;
+    }
+    let name_end = i
+        + 1
+        + chars[i + 1..]
+            .iter()
+            .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            .count();
+    (name_end > i + 1 && chars.get(name_end) == Some(&':') && chars.get(name_end + 1) == Some(&'`'))
+        .then_some(name_end + 1)
+}
+
+/// Inline markup starts at the start of a line or after whitespace or opening punctuation
+fn can_start(chars: &[char], i: usize) -> bool {
+    let before = i.checked_sub(1).map(|i| chars[i]);
+    before.is_none_or(|c| c.is_whitespace() || "'\"([{<-/:".contains(c))
+}
+
+/// Position of `close` ending markup whose text starts at `start`. The text can't start or
+/// end with whitespace and the end is followed by whitespace, punctuation or the end of
+/// the line. With `escapes`, a `close` after a backslash is part of the text.
+fn find_end(chars: &[char], start: usize, close: &str, escapes: bool) -> Option<usize> {
+    let close: Vec<char> = close.chars().collect();
+    if chars.get(start).is_none_or(|c| c.is_whitespace()) {
+        return None;
+    }
+
+    (start + 1..=chars.len().saturating_sub(close.len())).find(|&end| {
+        chars[end..].starts_with(&close)
+            && !chars[end - 1].is_whitespace()
+            && !(escapes && is_escaped(chars, end))
+            && chars
+                .get(end + close.len())
+                .is_none_or(|c| c.is_whitespace() || "'\")]}>-/:.,;!?\\_".contains(*c))
+    })
+}
+
+/// Whether the character at `i` follows an odd number of backslashes
+fn is_escaped(chars: &[char], i: usize) -> bool {
+    chars[..i].iter().rev().take_while(|&&c| c == '\\').count() % 2 == 1
+}
+
+/// `chars` with their backslash escapes applied
+fn unescape(chars: &[char]) -> String {
+    let mut out = String::new();
+    let mut chars = chars.iter();
+    while let Some(&c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DescriptionFormat::*;
+
+    #[test]
+    fn plain_markup() {
+        assert_eq!(normalize("**IR** mode", Plain), "IR mode");
+        assert_eq!(normalize("*slow* clock", Plain), "slow clock");
+        assert_eq!(normalize("set ``cr_en`` first", Plain), "set cr_en first");
+        assert_eq!(normalize("see :ref:`uart-lin`", Plain), "see uart-lin");
+        assert_eq!(
+            normalize("see `the SDK <https://x.y>`_", Plain),
+            "see the SDK"
+        );
+        assert_eq!(normalize("see `utx_config`_", Plain), "see utx_config");
+        assert_eq!(normalize("the |soc| clock", Plain), "the soc clock");
+    }
+
+    #[test]
+    fn markdown_markup() {
+        assert_eq!(normalize("**IR** mode", Markdown), "**IR** mode");
+        assert_eq!(normalize("*slow* clock", Markdown), "*slow* clock");
+        assert_eq!(
+            normalize("set ``cr_en`` first", Markdown),
+            "set `cr_en` first"
+        );
+        assert_eq!(normalize(":ref:`uart-lin`", Markdown), "`uart-lin`");
+        assert_eq!(
+            normalize("see `the SDK <https://x.y>`_", Markdown),
+            "see [the SDK](https://x.y)"
+        );
+    }
+
+    #[test]
+    fn not_markup() {
+        // Markup starts after whitespace or opening punctuation and needs an end
+        assert_eq!(normalize("a*b*c", Plain), "a*b*c");
+        assert_eq!(normalize("2 * 3", Plain), "2 * 3");
+        assert_eq!(normalize("** bold**", Plain), "** bold**");
+        assert_eq!(normalize("Vsync|Hsync", Plain), "Vsync|Hsync");
+        assert_eq!(normalize("(**IR**)", Plain), "(IR)");
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(normalize(r"\*not emphasis\*", Plain), "*not emphasis*");
+        assert_eq!(normalize(r"*a\*b* c", Plain), "a*b c");
+        assert_eq!(normalize(r"**x\\** y", Plain), r"x\ y");
+        // Inline literals keep their backslashes
+        assert_eq!(normalize(r"``C:\path\*`` z", Plain), r"C:\path\* z");
+        assert_eq!(normalize(r"``a\`` b", Markdown), r"`a\` b");
+    }
+
+    #[test]
+    fn lines_are_joined() {
+        assert_eq!(
+            normalize("Length of UART TX data\n  transfer (Unit: byte)\n", Plain),
+            "Length of UART TX data transfer (Unit: byte)"
+        );
+        assert_eq!(
+            normalize("\nFirst paragraph\ngoes on\n\n\nSecond\n\n", Plain),
+            "First paragraph goes on\n\nSecond"
+        );
+    }
+
+    #[test]
+    fn list_lines_stay() {
+        let text =
+            "TX mode\n2'd0 : Normal mode\n2'd1 : **IR**\nmode\n- bullet\n1. first\n(2) second";
+        assert_eq!(
+            normalize(text, Plain),
+            "TX mode\n2'd0 : Normal mode\n2'd1 : IR mode\n- bullet\n1. first\n(2) second"
+        );
+        assert_eq!(
+            normalize("TX mode\n2'd0 : Normal\n2'd1 : IR", Markdown),
+            "TX mode\n- 2'd0 : Normal\n- 2'd1 : IR"
+        );
+        assert!(!is_list_line("100. percent"));
+        assert!(!is_list_line("ab. cd"));
+    }
+}
@@ -0,0 +1,434 @@
+//! RST grid tables with any number of columns. Cells spanning several columns are read as
+//! one cell, rows spanning several lines as one row.
+
+use std::ops::Range;
+
+use anyhow::{anyhow, Result};
+
+/// A cell of a grid table
+#[derive(Debug, Clone)]
+pub(crate) struct GridCell {
+    /// Leftmost column the cell covers
+    pub column: usize,
+    /// Number of columns the cell covers
+    pub span: usize,
+    /// Trimmed lines of the cell, joined with newlines
+    pub text: String,
+    /// Byte offset of the cell's first line of text, in the source the table was cut from
+    pub offset: usize,
+}
+
+impl GridCell {
+    /// Bytes of the cell's first line of text
+    pub fn span(&self) -> Range<usize> {
+        self.offset..self.offset + self.text.lines().next().map_or(0, str::len)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct GridRow {
+    pub cells: Vec<GridCell>,
+}
+
+impl GridRow {
+    /// Text of the cell starting at `column`. Columns covered by a cell to their left read
+    /// as empty.
+    pub fn text(&self, column: Option<usize>) -> &str {
+        self.cell(column).map_or("", |cell| cell.text.as_str())
+    }
+
+    pub fn cell(&self, column: Option<usize>) -> Option<&GridCell> {
+        let column = column?;
+        self.cells.iter().find(|cell| cell.column == column)
+    }
+
+    /// Span of the cell starting at `column`, or of the row's first cell
+    pub fn span(&self, column: Option<usize>) -> Range<usize> {
+        self.cell(column)
+            .or(self.cells.first())
+            .map_or(0..0, GridCell::span)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct GridTable {
+    /// Header text of each column
+    pub header: Vec<String>,
+    pub rows: Vec<GridRow>,
+}
+
+impl GridTable {
+    /// Reads the grid table in `text`, which starts `offset` bytes into its source. The
+    /// header is the rows above the `=` rule, or the first row if there is none.
+    pub fn parse(text: &str, offset: usize) -> Result<GridTable> {
+        let lines: Vec<Line> = text
+            .split_inclusive('\n')
+            .scan(offset, |start, line| {
+                let current = *start;
+                *start += line.len();
+                Some(Line::new(line, current))
+            })
+            .filter(|line| !line.chars.is_empty())
+            .collect();
+
+        let boundaries = boundaries(&lines);
+        if boundaries.len() < 2 {
+            return Err(anyhow!("Grid table without columns"));
+        }
+
+        let mut rows = Vec::<GridRow>::new();
+        let mut header_rows = None;
+        let mut current: Option<GridRow> = None;
+        for line in &lines[1..] {
+            if line.is_border() {
+                let segments: Vec<&str> = (0..boundaries.len() - 1)
+                    .map(|i| line.segment(boundaries[i], boundaries[i + 1]))
+                    .collect();
+                // Only a rule across every column ends the row, partial rules below cells
+                // spanning several rows are read as part of them
+                if segments
+                    .iter()
+                    .all(|s| !s.is_empty() && s.chars().all(|c| c == '-' || c == '='))
+                {
+                    rows.extend(current.take());
+                    if segments.iter().any(|s| s.contains('=')) {
+                        header_rows = Some(rows.len());
+                    }
+                }
+                continue;
+            }
+
+            let row = current.get_or_insert_with(|| GridRow {
+                cells: line.cells(&boundaries),
+            });
+            for cell in &mut row.cells {
+                let (start, end) = (boundaries[cell.column], boundaries[cell.column + cell.span]);
+                let Some((cell_offset, cell_text)) = line.text(start, end) else {
+                    continue;
+                };
+                if cell.text.is_empty() {
+                    cell.offset = cell_offset;
+                } else {
+                    cell.text.push('\n');
+                }
+                cell.text.push_str(cell_text);
+            }
+        }
+        rows.extend(current.take());
+
+        let header_rows = header_rows.unwrap_or(1).min(rows.len());
+        let header = (0..boundaries.len() - 1)
+            .map(|column| {
+                rows[..header_rows]
+                    .iter()
+                    .filter_map(|row| {
+                        row.cells
+                            .iter()
+                            .find(|cell| cell.column <= column && column < cell.column + cell.span)
+                    })
+                    .map(|cell| cell.text.replace('\n', " "))
+                    .filter(|text| !text.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect();
+        rows.drain(..header_rows);
+
+        Ok(GridTable { header, rows })
+    }
+
+    /// First column whose header is one of `names`, ignoring case
+    pub fn column(&self, names: &[&str]) -> Option<usize> {
+        self.header.iter().position(|header| {
+            names
+                .iter()
+                .any(|name| header.trim().eq_ignore_ascii_case(name))
+        })
+    }
+}
+
+/// A line of a table, split into characters at their display columns. Indentation is
+/// dropped, every line of a table has the same.
+struct Line<'a> {
+    text: &'a str,
+    /// Display column, byte index into `text` and character
+    chars: Vec<(usize, usize, char)>,
+    offset: usize,
+}
+
+impl<'a> Line<'a> {
+    fn new(text: &'a str, offset: usize) -> Line<'a> {
+        let trimmed = text.trim_end_matches(['\r', '\n']).trim_end();
+        let indent = trimmed.len() - trimmed.trim_start().len();
+        let mut column = 0;
+        let chars = trimmed[indent..]
+            .char_indices()
+            .map(|(i, c)| {
+                let position = (column, indent + i, c);
+                column += display_width(c);
+                position
+            })
+            .collect();
+
+        Line {
+            text,
+            chars,
+            offset,
+        }
+    }
+
+    fn is_border(&self) -> bool {
+        self.chars.first().is_some_and(|&(_, _, c)| c == '+')
+            && self
+                .chars
+                .iter()
+                .all(|&(_, _, c)| matches!(c, '+' | '-' | '=' | ' '))
+    }
+
+    fn char_at(&self, column: usize) -> Option<char> {
+        self.chars
+            .iter()
+            .find(|&&(c, _, _)| c == column)
+            .map(|&(_, _, c)| c)
+    }
+
+    /// Text strictly between the display columns `start` and `end`
+    fn segment(&self, start: usize, end: usize) -> &'a str {
+        let mut inside = self
+            .chars
+            .iter()
+            .filter(|&&(column, _, _)| start < column && column < end);
+        let Some(&(_, first, _)) = inside.next() else {
+            return "";
+        };
+        let last = inside.next_back().map_or(first, |&(_, i, _)| i);
+        let last = last + self.text[last..].chars().next().map_or(0, char::len_utf8);
+        self.text[first..last].trim()
+    }
+
+    /// The trimmed text between `start` and `end` with its offset, if it isn't empty
+    fn text(&self, start: usize, end: usize) -> Option<(usize, &'a str)> {
+        let segment = self.segment(start, end);
+        // Rules below cells that end before the row does
+        if segment.chars().all(|c| c == '-' || c == '=') {
+            return None;
+        }
+        // `segment` borrows from `text`, its offset is where it starts in there
+        let index = segment.as_ptr() as usize - self.text.as_ptr() as usize;
+        Some((self.offset + index, segment))
+    }
+
+    /// Cells of a row whose first line is this one, a cell ends at the next `|`
+    fn cells(&self, boundaries: &[usize]) -> Vec<GridCell> {
+        let mut cells = Vec::new();
+        let mut column = 0;
+        for i in 1..boundaries.len() {
+            let last = i == boundaries.len() - 1;
+            if last || matches!(self.char_at(boundaries[i]), Some('|' | '+')) {
+                cells.push(GridCell {
+                    column,
+                    span: i - column,
+                    text: String::new(),
+                    offset: self.offset,
+                });
+                column = i;
+            }
+        }
+        cells
+    }
+}
+
+/// Display columns of the `+` joints of the table's rules
+fn boundaries(lines: &[Line]) -> Vec<usize> {
+    let mut boundaries: Vec<usize> = lines
+        .iter()
+        .filter(|line| line.is_border())
+        .flat_map(|line| {
+            line.chars
+                .iter()
+                .filter(|&&(_, _, c)| c == '+')
+                .map(|&(column, _, _)| column)
+        })
+        .collect();
+    boundaries.sort_unstable();
+    boundaries.dedup();
+    boundaries
+}
+
+/// Columns a character takes up, wide East Asian characters take two
+pub(crate) fn display_width(c: char) -> usize {
+    match c as u32 {
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6 => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(row: &GridRow) -> Vec<(usize, usize, &str)> {
+        row.cells
+            .iter()
+            .map(|cell| (cell.column, cell.span, cell.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn four_columns() {
+        let table = GridTable::parse(
+            "+------+------+------+-------------+\n\
+             | Bit  | Name | Type | Description |\n\
+             +------+------+------+-------------+\n\
+             | 0    | en   | r/w  | Enable      |\n\
+             +------+------+------+-------------+\n",
+            0,
+        )
+        .unwrap();
+        assert_eq!(table.header, ["Bit", "Name", "Type", "Description"]);
+        assert_eq!(table.rows.len(), 1);
+        assert_eq!(
+            texts(&table.rows[0]),
+            [(0, 1, "0"), (1, 1, "en"), (2, 1, "r/w"), (3, 1, "Enable")]
+        );
+        assert_eq!(table.column(&["type", "Access"]), Some(2));
+        assert_eq!(table.column(&["Reset"]), None);
+    }
+
+    #[test]
+    fn six_columns_with_header_rule() {
+        let table = GridTable::parse(
+            "+-----+------+------+-------+------+------+\n\
+             | Bit | Name | Type | Reset | Note | Desc |\n\
+             +=====+======+======+=======+======+======+\n\
+             | 1:0 | mode | r    | 2'd1  |      | Mode |\n\
+             +-----+------+------+-------+------+------+\n\
+             | 2   | en   | w1c  | 1'b0  | x    | En   |\n\
+             +-----+------+------+-------+------+------+\n",
+            0,
+        )
+        .unwrap();
+        assert_eq!(
+            table.header,
+            ["Bit", "Name", "Type", "Reset", "Note", "Desc"]
+        );
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(table.rows[0].text(Some(3)), "2'd1");
+        assert_eq!(table.rows[0].text(Some(4)), "");
+        assert_eq!(table.rows[1].text(Some(2)), "w1c");
+        assert_eq!(table.rows[1].text(None), "");
+    }
+
+    #[test]
+    fn header_rows_above_the_rule() {
+        let table = GridTable::parse(
+            "+-----+-------+\n\
+             | Bit | Reset |\n\
+             +-----+-------+\n\
+             |     | value |\n\
+             +=====+=======+\n\
+             | 0   | 1'b0  |\n\
+             +-----+-------+\n",
+            0,
+        )
+        .unwrap();
+        assert_eq!(table.header, ["Bit", "Reset value"]);
+        assert_eq!(table.column(&["Reset value"]), Some(1));
+        assert_eq!(table.rows.len(), 1);
+    }
+
+    #[test]
+    fn column_spans() {
+        let table = GridTable::parse(
+            "+-----+------+------+\n\
+             | Bit | Name | Desc |\n\
+             +=====+======+======+\n\
+             | 0   | Reserved    |\n\
+             +-----+------+------+\n",
+            0,
+        )
+        .unwrap();
+        assert_eq!(texts(&table.rows[0]), [(0, 1, "0"), (1, 2, "Reserved")]);
+        // Columns covered by the spanning cell read as empty
+        assert_eq!(table.rows[0].text(Some(2)), "");
+    }
+
+    #[test]
+    fn row_spans() {
+        let table = GridTable::parse(
+            "+-------+-------------+\n\
+             | Bit   | Description |\n\
+             +=======+=============+\n\
+             | 31:16 | Length      |\n\
+             +       +-------------+\n\
+             |       | in bytes    |\n\
+             +-------+-------------+\n\
+             | 15:0  | Count       |\n\
+             +-------+-------------+\n",
+            0,
+        )
+        .unwrap();
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(
+            texts(&table.rows[0]),
+            [(0, 1, "31:16"), (1, 1, "Length\nin bytes")]
+        );
+        assert_eq!(table.rows[1].text(Some(0)), "15:0");
+    }
+
+    #[test]
+    fn wide_characters() {
+        let text = "+------+----------+\n\
+                    | 位   | 描述     |\n\
+                    +======+==========+\n\
+                    | 0    | 使能发送 |\n\
+                    +------+----------+\n";
+        let table = GridTable::parse(text, 10).unwrap();
+        assert_eq!(table.header, ["位", "描述"]);
+        let cell = table.rows[0].cell(Some(1)).unwrap();
+        assert_eq!(cell.text, "使能发送");
+        assert_eq!(
+            &text[cell.span().start - 10..cell.span().end - 10],
+            "使能发送"
+        );
+    }
+
+    #[test]
+    fn bars_inside_cells() {
+        let table = GridTable::parse(
+            "+-----+-------------+\n\
+             | Bit | Description |\n\
+             +=====+=============+\n\
+             | 0   | Vsync|Hsync |\n\
+             +-----+-------------+\n",
+            0,
+        )
+        .unwrap();
+        assert_eq!(texts(&table.rows[0]), [(0, 1, "0"), (1, 1, "Vsync|Hsync")]);
+    }
+
+    #[test]
+    fn offsets() {
+        let text = "  +-----+------+\n  | Bit | Name |\n  +-----+------+\n  | 3   | en   |\n  +-----+------+\n";
+        let table = GridTable::parse(text, 100).unwrap();
+        let span = table.rows[0].span(Some(1));
+        assert_eq!(&text[span.start - 100..span.end - 100], "en");
+        // Missing columns fall back to the first cell
+        assert_eq!(table.rows[0].span(Some(5)), table.rows[0].span(Some(0)));
+    }
+
+    #[test]
+    fn tables_without_columns() {
+        assert!(GridTable::parse("+\n", 0).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::manifest::Language;
+
+/// Register header folders, relative to the M1s_BL808_SDK root
+const HEADER_FOLDERS: [&str; 3] = [
+    "components/platform/soc/bl808/bl808_std/BL808_BSP_Driver/dsp2_reg/",
+    "components/platform/soc/bl808/bl808_std/BL808_BSP_Driver/Peripherals/",
+    "components/platform/soc/bl808/bl808_e907_std/bl808_bsp_driver/regs/",
+];
+
+/// Register header folders, relative to the bl_mcu_sdk root
+const DEFINES_FOLDERS: [&str; 1] = ["drivers/soc/bl808/std/include/hardware/"];
+
+/// Headers with the `*_BASE` defines of the memory map
+const MEMORY_MAP_HEADERS: [&str; 2] = ["bl808.h", "bl808_memorymap.h"];
+
+/// Headers with the `IRQn_Type` enum and `*_IRQn` defines
+const IRQ_HEADERS: [&str; 1] = ["bl808.h"];
+
+/// Driver source folders, relative to the M1s SDK and bl_mcu_sdk roots
+const M1S_DRIVER_FOLDERS: [&str; 1] =
+    ["components/platform/soc/bl808/bl808_std/BL808_BSP_Driver/StdDriver"];
+const MCU_SDK_DRIVER_FOLDERS: [&str; 2] = ["drivers/soc/bl808/std/src", "drivers/lhal/src"];
+
+/// Checkouts of the vendor repositories the register descriptions are read from
+#[derive(Debug, Clone)]
+pub struct SourceRoots {
+    pub bl_docs: PathBuf,
+    pub m1s_sdk: PathBuf,
+    pub bl_mcu_sdk: PathBuf,
+}
+
+impl SourceRoots {
+    /// Uses the submodule checkouts of this repository
+    pub fn from_repo_root(repo_root: &Path) -> SourceRoots {
+        SourceRoots {
+            bl_docs: repo_root.join("bl_docs"),
+            m1s_sdk: repo_root.join("M1s_BL808_SDK"),
+            bl_mcu_sdk: repo_root.join("bl_mcu_sdk"),
+        }
+    }
+
+    /// Returns the roots that don't exist on disk
+    pub fn missing(&self) -> Vec<&Path> {
+        [&self.bl_docs, &self.m1s_sdk, &self.bl_mcu_sdk]
+            .into_iter()
+            .filter(|root| !root.is_dir())
+            .map(|root| root.as_path())
+            .collect()
+    }
+
+    pub fn rst_path(&self, filename: &str, language: Language) -> PathBuf {
+        self.bl_docs
+            .join("BL808_RM")
+            .join(language.code())
+            .join("RST")
+            .join(filename)
+    }
+
+    /// Searches the SDK header folders for `filename`
+    pub fn header_path(&self, filename: &str) -> Option<PathBuf> {
+        HEADER_FOLDERS
+            .iter()
+            .map(|folder| self.m1s_sdk.join(folder).join(filename))
+            .find(|file_path| file_path.exists())
+    }
+
+    /// Memory map headers of both SDKs, M1s SDK first
+    pub fn memory_map_headers(&self) -> Vec<PathBuf> {
+        self.soc_files(&MEMORY_MAP_HEADERS)
+    }
+
+    /// Interrupt number headers of both SDKs, M1s SDK first
+    pub fn irq_headers(&self) -> Vec<PathBuf> {
+        self.soc_files(&IRQ_HEADERS)
+    }
+
+    /// C sources of the SDK drivers
+    pub fn driver_sources(&self) -> Vec<PathBuf> {
+        let m1s = M1S_DRIVER_FOLDERS.iter().map(|f| self.m1s_sdk.join(f));
+        let mcu_sdk = MCU_SDK_DRIVER_FOLDERS
+            .iter()
+            .map(|f| self.bl_mcu_sdk.join(f));
+
+        m1s.chain(mcu_sdk)
+            .flat_map(|folder| find_files(&folder, &|name| name.ends_with(".c")))
+            .collect()
+    }
+
+    /// Files called any of `filenames` in the BL808 folders of the SDKs
+    fn soc_files(&self, filenames: &[&str]) -> Vec<PathBuf> {
+        let soc_folders = [
+            self.m1s_sdk.join("components/platform/soc/bl808"),
+            self.bl_mcu_sdk.join("drivers/soc/bl808"),
+        ];
+
+        soc_folders
+            .iter()
+            .flat_map(|folder| {
+                filenames
+                    .iter()
+                    .flat_map(|filename| find_files(folder, &|name| name == *filename))
+            })
+            .collect()
+    }
+
+    /// Searches the bl_mcu_sdk header folders for `filename`
+    pub fn defines_path(&self, filename: &str) -> Option<PathBuf> {
+        DEFINES_FOLDERS
+            .iter()
+            .map(|folder| self.bl_mcu_sdk.join(folder).join(filename))
+            .find(|file_path| file_path.exists())
+    }
+}
+
+/// Every file below `folder` whose name `matches`, sorted
+fn find_files(folder: &Path, matches: &dyn Fn(&str) -> bool) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let Ok(entries) = fs::read_dir(folder) else {
+        return found;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            found.append(&mut find_files(&path, matches));
+        } else if path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(matches)
+        {
+            found.push(path);
+        }
+    }
+
+    found.sort();
+    found
+}
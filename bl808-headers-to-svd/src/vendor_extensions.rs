@@ -0,0 +1,76 @@
+//! Device level `<vendorExtensions>`, which svd-rs can't represent. The SVD schema allows
+//! them only once, at the end of `<device>`.
+
+use crate::register_source::Translation;
+
+/// Appends `translations` to the encoded SVD `svd` as `<vendorExtensions>`, one
+/// `<description>` per element named by its path:
+///
+/// ```xml
+/// <vendorExtensions>
+///   <translations>
+///     <description path="UART0.utx_config" xml:lang="zh-CN">...</description>
+///   </translations>
+/// </vendorExtensions>
+/// ```
+pub fn with_translations(svd: String, translations: &[Translation]) -> String {
+    let Some(end) = svd.rfind("</device>") else {
+        return svd;
+    };
+    if translations.is_empty() {
+        return svd;
+    }
+
+    let mut extensions = String::from("  <vendorExtensions>\n    <translations>\n");
+    for translation in translations {
+        extensions.push_str(&format!(
+            "      <description path=\"{}\" xml:lang=\"{}\">{}</description>\n",
+            escape(&translation.path),
+            translation.language.xml_lang(),
+            escape(&translation.description)
+        ));
+    }
+    extensions.push_str("    </translations>\n  </vendorExtensions>\n");
+
+    let mut svd = svd;
+    svd.insert_str(end, &extensions);
+    svd
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::Language;
+
+    #[test]
+    fn translations() {
+        let translations = [Translation {
+            path: "UART0.utx_config".to_string(),
+            language: Language::ZhCn,
+            description: "发送 <TX> & \"配置\"".to_string(),
+        }];
+        let svd = with_translations("<device>\n</device>\n".to_string(), &translations);
+        assert_eq!(
+            svd,
+            "<device>\n  <vendorExtensions>\n    <translations>\n      \
+             <description path=\"UART0.utx_config\" xml:lang=\"zh-CN\">\
+             发送 &lt;TX&gt; &amp; &quot;配置&quot;</description>\n    \
+             </translations>\n  </vendorExtensions>\n</device>\n"
+        );
+        assert_eq!(
+            with_translations("<device/>".to_string(), &translations),
+            "<device/>"
+        );
+        assert_eq!(
+            with_translations("<device></device>".to_string(), &[]),
+            "<device></device>"
+        );
+    }
+}